@@ -0,0 +1,248 @@
+use crate::{Button, Finger, Hand, Key, Keyboard, Layer, Layout, MatrixPosition, Position};
+
+// The characters of the US layout on the rows of the board, starting at column 0. Row 0 is the
+// number row and row 3 the bottom row, ' ' is a column without a key
+const US_ROWS: [&str; 4] = [
+    "`1234567890-=",
+    " qwertyuiop[]\\",
+    " asdfghjkl;'",
+    " zxcvbnm,./",
+];
+
+// Where column 0 of each row starts, in key units
+const ROW_OFFSETS: [f64; 4] = [0.0, 0.5, 0.75, 1.25];
+
+/// The physical boards operating system layouts are made for. Buttons only cover the keys that
+/// type characters, the matrix row is the row of the board from the number row down and the
+/// matrix column counts keys from the left, with the Q, A and Z keys in column 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Board {
+    /// 47 keys, with backslash above Enter
+    Ansi,
+    /// 48 keys, with a key left of Z and the one right of the apostrophe in place of the ANSI
+    /// backslash
+    Iso,
+}
+
+impl Board {
+    pub fn keyboard(self) -> Keyboard {
+        let mut buttons = Vec::new();
+        for (row, us_row) in US_ROWS.iter().enumerate() {
+            for col in 0..=us_row.chars().count() {
+                let matrix_position = MatrixPosition {
+                    col: col as u8,
+                    row: row as u8,
+                };
+                if !self.has_key(matrix_position) {
+                    continue;
+                }
+
+                let position = Position {
+                    x: ROW_OFFSETS[row] + col as f64,
+                    y: row as f64,
+                };
+                let (hand, finger, home_col) = match col {
+                    0 | 1 => (Hand::Left, Finger::Pinky, 1),
+                    2 => (Hand::Left, Finger::Ring, 2),
+                    3 => (Hand::Left, Finger::Middle, 3),
+                    4 | 5 => (Hand::Left, Finger::Pointer, 4),
+                    6 | 7 => (Hand::Right, Finger::Pointer, 7),
+                    8 => (Hand::Right, Finger::Middle, 8),
+                    9 => (Hand::Right, Finger::Ring, 9),
+                    _ => (Hand::Right, Finger::Pinky, 10),
+                };
+                let home_offset = Position {
+                    x: position.x - (ROW_OFFSETS[2] + home_col as f64),
+                    y: position.y - 2.0,
+                };
+                buttons.push(Button {
+                    hand,
+                    finger,
+                    position,
+                    matrix_position,
+                    home_offset,
+                    cost: home_offset.x.hypot(home_offset.y),
+                });
+            }
+        }
+
+        Keyboard { buttons }
+    }
+
+    fn has_key(self, matrix_position: MatrixPosition) -> bool {
+        match (matrix_position.row, matrix_position.col) {
+            (1, 13) => self == Board::Ansi,
+            (2, 12) | (3, 0) => self == Board::Iso,
+            (row, col) => us_char(MatrixPosition { col, row }).is_some(),
+        }
+    }
+
+    /// The US layout as a single layer
+    pub fn us_layout(self) -> Layout {
+        let keys = self
+            .keyboard()
+            .buttons
+            .iter()
+            .map(|button| us_char(button.matrix_position).map_or(Key::None, Key::Char))
+            .collect();
+        Layout {
+            layers: vec![Layer {
+                name: "base".to_string(),
+                keys,
+            }],
+        }
+    }
+}
+
+/// The character the US layout types at a position of an ANSI or ISO board, the ISO only keys
+/// type backslash like on a US layout used with an ISO board
+pub fn us_char(matrix_position: MatrixPosition) -> Option<char> {
+    match (matrix_position.row, matrix_position.col) {
+        (2, 12) | (3, 0) => Some('\\'),
+        (row, col) => US_ROWS
+            .get(row as usize)?
+            .chars()
+            .nth(col as usize)
+            .filter(|&c| c != ' '),
+    }
+}
+
+/// The character the US layout types with shift on the key that types `c` without it. Characters
+/// that aren't on the US layout are the same with and without shift, except for letters
+pub fn shifted(c: char) -> char {
+    const PAIRS: [(char, char); 21] = [
+        ('`', '~'),
+        ('1', '!'),
+        ('2', '@'),
+        ('3', '#'),
+        ('4', '$'),
+        ('5', '%'),
+        ('6', '^'),
+        ('7', '&'),
+        ('8', '*'),
+        ('9', '('),
+        ('0', ')'),
+        ('-', '_'),
+        ('=', '+'),
+        ('[', '{'),
+        (']', '}'),
+        ('\\', '|'),
+        (';', ':'),
+        ('\'', '"'),
+        (',', '<'),
+        ('.', '>'),
+        ('/', '?'),
+    ];
+
+    match PAIRS.iter().find(|(unshifted, _)| *unshifted == c) {
+        Some((_, shifted)) => *shifted,
+        None => {
+            let mut upper = c.to_uppercase();
+            match (upper.next(), upper.next()) {
+                (Some(upper), None) => upper,
+                _ => c,
+            }
+        }
+    }
+}
+
+/// A dead key: the accent it types before a character it can't go on, its name and the
+/// characters it goes on, along with the accented characters
+pub struct DeadKey {
+    pub accent: char,
+    pub name: &'static str,
+    pub bases: &'static str,
+    pub accented: &'static str,
+}
+
+impl DeadKey {
+    /// Pairs of characters typed after the dead key and what the two type together
+    pub fn compositions(&self) -> impl Iterator<Item = (char, char)> {
+        self.bases.chars().zip(self.accented.chars())
+    }
+}
+
+/// The dead keys the formats for operating systems support, `Key::Dead` takes their accents
+pub const DEAD_KEYS: [DeadKey; 6] = [
+    DeadKey {
+        accent: '´',
+        name: "acute",
+        bases: "aeiouyAEIOUY",
+        accented: "áéíóúýÁÉÍÓÚÝ",
+    },
+    DeadKey {
+        accent: '`',
+        name: "grave",
+        bases: "aeiouAEIOU",
+        accented: "àèìòùÀÈÌÒÙ",
+    },
+    DeadKey {
+        accent: '^',
+        name: "circumflex",
+        bases: "aeiouAEIOU",
+        accented: "âêîôûÂÊÎÔÛ",
+    },
+    DeadKey {
+        accent: '~',
+        name: "tilde",
+        bases: "anoANO",
+        accented: "ãñõÃÑÕ",
+    },
+    DeadKey {
+        accent: '¨',
+        name: "diaeresis",
+        bases: "aeiouyAEIOUY",
+        accented: "äëïöüÿÄËÏÖÜŸ",
+    },
+    DeadKey {
+        accent: '¸',
+        name: "cedilla",
+        bases: "cC",
+        accented: "çÇ",
+    },
+];
+
+pub fn dead_key(accent: char) -> Option<&'static DeadKey> {
+    DEAD_KEYS.iter().find(|dead_key| dead_key.accent == accent)
+}
+
+/// Names of the levels, for errors
+pub(crate) const LEVEL_NAMES: [&str; 4] = ["base", "shift", "altgr", "altgr shift"];
+
+/// What a button types without modifiers, with shift, with AltGr and with both
+pub(crate) type Levels = [Key; 4];
+
+/// The levels of every button of a layout for an operating system. The first layer is typed
+/// without modifiers, layers named "shift", "altgr" and "altgr shift" with those modifiers.
+/// Without a shift layer the keys type what shift types on the US layout, uppercase for letters,
+/// and the same goes for AltGr with and without shift. Transparent keys count as missing
+pub(crate) fn levels(layout: &Layout) -> Vec<Levels> {
+    let layer = |name: &str| {
+        layout
+            .layers
+            .iter()
+            .find(|layer| layer.name.eq_ignore_ascii_case(name))
+    };
+    let key = |layer: Option<&Layer>, i: usize| {
+        layer
+            .and_then(|layer| layer.keys.get(i))
+            .filter(|key| **key != Key::Transparent)
+            .cloned()
+    };
+    let shift = |key: &Key| match key {
+        Key::Char(c) => Key::Char(shifted(*c)),
+        key => key.clone(),
+    };
+
+    let (shift_layer, altgr_layer, altgr_shift_layer) =
+        (layer("shift"), layer("altgr"), layer("altgr shift"));
+    (0..layout.layers.first().map_or(0, |base| base.keys.len()))
+        .map(|i| {
+            let base = layout.layers[0].keys[i].clone();
+            let shifted_base = key(shift_layer, i).unwrap_or_else(|| shift(&base));
+            let altgr = key(altgr_layer, i).unwrap_or(Key::None);
+            let shifted_altgr = key(altgr_shift_layer, i).unwrap_or_else(|| shift(&altgr));
+            [base, shifted_base, altgr, shifted_altgr]
+        })
+        .collect()
+}
@@ -0,0 +1,277 @@
+use std::fmt::Write;
+
+use crate::board::{self, shifted};
+use crate::{ExportError, Key, Keyboard, Layout, MatrixPosition, Modifier, NamedKey};
+
+// How long a tap-hold key has to be held to hold, in milliseconds
+const TAP_HOLD_TIMEOUT: u32 = 200;
+
+// Names of the keys of the US layout that type characters other than letters and digits
+const KEY_NAMES: [(char, &str); 12] = [
+    (' ', "spc"),
+    ('`', "grv"),
+    ('-', "-"),
+    ('=', "="),
+    ('[', "["),
+    (']', "]"),
+    ('\\', "\\"),
+    (';', ";"),
+    ('\'', "'"),
+    (',', ","),
+    ('.', "."),
+    ('/', "/"),
+];
+
+/// The software remappers with the same configuration language
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Remapper {
+    Kanata,
+    /// Needs the input device in defcfg filled in
+    Kmonad,
+}
+
+/// Writes the layout as a remapper configuration for a keyboard from `board::Board`, with the
+/// keys of the board in defsrc and a deflayer per layer. Characters are typed through the keys
+/// that type them on the US layout, with shift when needed, so the operating system has to use
+/// the US layout. Mod-taps and layer-taps become aliases
+pub fn config(
+    keyboard: &Keyboard,
+    layout: &Layout,
+    remapper: Remapper,
+) -> Result<String, ExportError> {
+    layout.check(keyboard)?;
+    let layer_names = layout
+        .layer_identifiers()
+        .iter()
+        .map(|name| name.to_lowercase())
+        .collect::<Vec<_>>();
+    let rows = keyboard.matrix_rows();
+
+    let source_rows = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&i| {
+                    let matrix_position = keyboard.buttons[i].matrix_position;
+                    source_key(matrix_position, remapper)
+                        .ok_or(ExportError::UnsupportedButton { matrix_position })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut aliases = Vec::new();
+    let mut layer_rows = Vec::new();
+    for layer in &layout.layers {
+        layer_rows.push(layer.key_rows(&rows, |key| {
+            let action = action(key, &layer_names, remapper)?;
+            if !matches!(key, Key::LayerTap { .. } | Key::ModTap { .. }) {
+                return Some(action);
+            }
+
+            if let Some((name, _)) = aliases.iter().find(|(_, alias)| *alias == action) {
+                return Some(format!("@{}", name));
+            }
+
+            // Taps that only differ in characters that can't be in a name are numbered
+            let name = alias_name(key, &layer_names, remapper)?;
+            let name = (1..)
+                .map(|n| match n {
+                    1 => name.clone(),
+                    n => format!("{}_{}", name, n),
+                })
+                .find(|name| aliases.iter().all(|(alias_name, _)| alias_name != name))
+                .unwrap();
+            aliases.push((name.clone(), action));
+            Some(format!("@{}", name))
+        })?);
+    }
+
+    // Every key is as wide as the widest one in its place, so that the layers line up
+    let widths = rows
+        .iter()
+        .enumerate()
+        .map(|(row, keys)| {
+            (0..keys.len())
+                .map(|col| {
+                    layer_rows
+                        .iter()
+                        .chain([&source_rows])
+                        .map(|rows| rows[row][col].chars().count())
+                        .max()
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let write_rows = |config: &mut String, key_rows: &[Vec<String>]| {
+        for (keys, widths) in key_rows.iter().zip(&widths) {
+            let line = keys
+                .iter()
+                .zip(widths)
+                .map(|(key, &width)| format!("{:<width$}", key, width = width))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(config, "  {}", line.trim_end()).unwrap();
+        }
+    };
+
+    let mut config = String::new();
+    match remapper {
+        Remapper::Kanata => {
+            writeln!(config, "(defcfg").unwrap();
+            writeln!(config, "  process-unmapped-keys yes").unwrap();
+            writeln!(config, ")").unwrap();
+        }
+        Remapper::Kmonad => {
+            writeln!(config, "(defcfg").unwrap();
+            writeln!(
+                config,
+                "  input  (device-file \"/dev/input/by-id/CHANGE-ME-event-kbd\")"
+            )
+            .unwrap();
+            writeln!(config, "  output (uinput-sink \"kmonad\")").unwrap();
+            writeln!(config, "  fallthrough true").unwrap();
+            writeln!(config, ")").unwrap();
+        }
+    }
+
+    writeln!(config).unwrap();
+    writeln!(config, "(defsrc").unwrap();
+    write_rows(&mut config, &source_rows);
+    writeln!(config, ")").unwrap();
+    if !aliases.is_empty() {
+        writeln!(config).unwrap();
+        writeln!(config, "(defalias").unwrap();
+        for (name, action) in &aliases {
+            writeln!(config, "  {} {}", name, action).unwrap();
+        }
+
+        writeln!(config, ")").unwrap();
+    }
+
+    for (name, key_rows) in layer_names.iter().zip(&layer_rows) {
+        writeln!(config).unwrap();
+        writeln!(config, "(deflayer {}", name).unwrap();
+        write_rows(&mut config, key_rows);
+        writeln!(config, ")").unwrap();
+    }
+
+    Ok(config)
+}
+
+// Name of the key at a position of an ANSI or ISO board
+fn source_key(matrix_position: MatrixPosition, remapper: Remapper) -> Option<String> {
+    match (matrix_position.row, matrix_position.col) {
+        (3, 0) => Some("102d".to_string()),
+        _ => key_name(board::us_char(matrix_position)?, remapper),
+    }
+}
+
+// Name of the key that types the character on the US layout without shift
+fn key_name(c: char, remapper: Remapper) -> Option<String> {
+    match c {
+        'a'..='z' | '0'..='9' => Some(c.to_string()),
+        '\\' if remapper == Remapper::Kmonad => Some("\\\\".to_string()),
+        _ => KEY_NAMES
+            .iter()
+            .find(|(key_char, _)| *key_char == c)
+            .map(|(_, name)| name.to_string()),
+    }
+}
+
+// What the key does, in a deflayer or an alias
+fn action(key: &Key, layer_names: &[String], remapper: Remapper) -> Option<String> {
+    let layer_name = |layer: usize| layer_names.get(layer);
+    let while_held = match remapper {
+        Remapper::Kanata => "layer-while-held",
+        Remapper::Kmonad => "layer-toggle",
+    };
+    let tap_hold = |tap: &Key, hold: String| {
+        let tap = action(tap, layer_names, remapper)?;
+        Some(match remapper {
+            Remapper::Kanata => format!(
+                "(tap-hold {} {} {} {})",
+                TAP_HOLD_TIMEOUT, TAP_HOLD_TIMEOUT, tap, hold
+            ),
+            Remapper::Kmonad => {
+                format!(
+                    "(tap-hold-next-release {} {} {})",
+                    TAP_HOLD_TIMEOUT, tap, hold
+                )
+            }
+        })
+    };
+
+    match key {
+        Key::None => Some("XX".to_string()),
+        Key::Transparent => Some("_".to_string()),
+        Key::Char(c) => key_name(*c, remapper)
+            .or_else(|| {
+                let unshifted = ('\u{20}'..='\u{7e}').find(|&unshifted| {
+                    unshifted != *c
+                        && shifted(unshifted) == *c
+                        && key_name(unshifted, remapper).is_some()
+                })?;
+                Some(format!("S-{}", key_name(unshifted, remapper)?))
+            })
+            .or_else(|| {
+                (remapper == Remapper::Kanata && !c.is_ascii()).then(|| format!("(unicode {})", c))
+            }),
+        Key::Named(named_key) => Some(
+            match named_key {
+                NamedKey::Space => "spc",
+                NamedKey::Enter => "ret",
+                NamedKey::Backspace => "bspc",
+                NamedKey::Delete => "del",
+                NamedKey::Tab => "tab",
+                NamedKey::Escape => "esc",
+                NamedKey::CapsLock => "caps",
+                NamedKey::Left => "left",
+                NamedKey::Right if remapper == Remapper::Kanata => "rght",
+                NamedKey::Right => "right",
+                NamedKey::Up => "up",
+                NamedKey::Down => "down",
+                NamedKey::Home => "home",
+                NamedKey::End => "end",
+                NamedKey::PageUp => "pgup",
+                NamedKey::PageDown => "pgdn",
+            }
+            .to_string(),
+        ),
+        Key::Modifier(modifier) => Some(modifier_name(*modifier).to_string()),
+        Key::Momentary(layer) => Some(format!("({} {})", while_held, layer_name(*layer)?)),
+        Key::Toggle(layer) => Some(format!("(layer-switch {})", layer_name(*layer)?)),
+        Key::LayerTap { layer, tap } => {
+            tap_hold(tap, format!("({} {})", while_held, layer_name(*layer)?))
+        }
+        Key::ModTap { modifier, tap } => tap_hold(tap, modifier_name(*modifier).to_string()),
+        Key::Dead(_) => None,
+    }
+}
+
+// Name of the alias of a mod-tap or layer-tap, like met_a for the left GUI on the A key
+fn alias_name(key: &Key, layer_names: &[String], remapper: Remapper) -> Option<String> {
+    let (hold, tap) = match key {
+        Key::LayerTap { layer, tap } => (layer_names.get(*layer)?.clone(), tap),
+        Key::ModTap { modifier, tap } => (modifier_name(*modifier).to_string(), tap),
+        _ => return None,
+    };
+    let tap = action(tap, layer_names, remapper)?
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    Some(format!("{}_{}", hold, tap))
+}
+
+fn modifier_name(modifier: Modifier) -> &'static str {
+    match modifier {
+        Modifier::LeftShift => "lsft",
+        Modifier::LeftControl => "lctl",
+        Modifier::LeftAlt => "lalt",
+        Modifier::LeftGui => "lmet",
+        Modifier::RightShift => "rsft",
+        Modifier::RightControl => "rctl",
+        Modifier::RightAlt => "ralt",
+        Modifier::RightGui => "rmet",
+    }
+}
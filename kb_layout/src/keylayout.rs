@@ -0,0 +1,262 @@
+use std::fmt::Write;
+
+use crate::board::{self, Levels, LEVEL_NAMES};
+use crate::{svg::escape, ExportError, Key, Keyboard, Layout, MatrixPosition};
+
+// Mac key codes of the keys of each row, starting at the first column with a key
+const KEY_CODES: [(u8, &[u8]); 4] = [
+    (0, &[50, 18, 19, 20, 21, 23, 22, 26, 28, 25, 29, 27, 24]),
+    (1, &[12, 13, 14, 15, 17, 16, 32, 34, 31, 35, 33, 30, 42]),
+    (1, &[0, 1, 2, 3, 5, 4, 38, 40, 37, 41, 39, 42]),
+    (0, &[10, 6, 7, 8, 9, 11, 45, 46, 43, 47, 44]),
+];
+const SPACE_KEY_CODE: u8 = 49;
+
+// A key map: the modifier combinations that select it and the level of each key on it
+struct KeyMap {
+    modifiers: &'static [&'static str],
+    level: fn(&Levels) -> &Key,
+}
+
+const BASE: KeyMap = KeyMap {
+    modifiers: &[""],
+    level: |levels| &levels[0],
+};
+const SHIFT: KeyMap = KeyMap {
+    modifiers: &["anyShift caps?"],
+    level: |levels| &levels[1],
+};
+// Caps Lock types the shifted letters and nothing else
+const CAPS: KeyMap = KeyMap {
+    modifiers: &["caps"],
+    level: |levels| match (&levels[0], &levels[1]) {
+        (Key::Char(base), shifted) if base.is_alphabetic() => shifted,
+        (base, _) => base,
+    },
+};
+const OPTION: KeyMap = KeyMap {
+    modifiers: &["anyOption caps?"],
+    level: |levels| &levels[2],
+};
+const SHIFT_OPTION: KeyMap = KeyMap {
+    modifiers: &["anyShift anyOption caps?"],
+    level: |levels| &levels[3],
+};
+// Shortcuts use the characters of the base level
+const COMMAND: KeyMap = KeyMap {
+    modifiers: &[
+        "command anyShift? anyOption? caps? anyControl?",
+        "anyControl anyShift? anyOption? caps?",
+    ],
+    level: |levels| &levels[0],
+};
+
+/// Writes the layout as a macOS .keylayout file for a keyboard from `board::Board`, with key
+/// maps for shift, Caps Lock, command and, when the layout has an AltGr layer, Option with and
+/// without shift for the AltGr levels. The levels come from `board::levels`. Install it in
+/// ~/Library/Keyboard Layouts
+pub fn keylayout(
+    keyboard: &Keyboard,
+    layout: &Layout,
+    description: &str,
+) -> Result<String, ExportError> {
+    layout.check(keyboard)?;
+    let levels = board::levels(layout);
+    let key_maps = if levels.iter().any(|levels| levels[2] != Key::None) {
+        vec![BASE, SHIFT, CAPS, OPTION, SHIFT_OPTION, COMMAND]
+    } else {
+        vec![BASE, SHIFT, CAPS, COMMAND]
+    };
+
+    let key_codes = keyboard
+        .buttons
+        .iter()
+        .map(|button| {
+            key_code(button.matrix_position).ok_or(ExportError::UnsupportedButton {
+                matrix_position: button.matrix_position,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for levels in &levels {
+        for (key, level) in levels.iter().zip(LEVEL_NAMES) {
+            let supported = match key {
+                Key::None | Key::Char(_) => true,
+                Key::Dead(accent) => board::dead_key(*accent).is_some(),
+                _ => false,
+            };
+            if !supported {
+                return Err(ExportError::UnsupportedKey {
+                    layer: level.to_string(),
+                    key: key.clone(),
+                });
+            }
+        }
+    }
+
+    // Characters a dead key of the layout goes on are typed through actions, which output the
+    // accented character after the dead key
+    let dead_keys = board::DEAD_KEYS
+        .iter()
+        .filter(|dead_key| {
+            levels
+                .iter()
+                .flatten()
+                .any(|key| *key == Key::Dead(dead_key.accent))
+        })
+        .collect::<Vec<_>>();
+    let mut accented_chars = Vec::new();
+    for levels in &levels {
+        for key in levels {
+            if let Key::Char(c) = key {
+                let accented = dead_keys.iter().any(|dead_key| dead_key.bases.contains(*c));
+                if accented && !accented_chars.contains(c) {
+                    accented_chars.push(*c);
+                }
+            }
+        }
+    }
+
+    let mut keylayout = String::new();
+    writeln!(keylayout, r#"<?xml version="1.1" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        keylayout,
+        r#"<!DOCTYPE keyboard SYSTEM "file://localhost/System/Library/DTDs/KeyboardLayout.dtd">"#
+    )
+    .unwrap();
+    writeln!(
+        keylayout,
+        r#"<keyboard group="126" id="{}" name="{}" maxout="1">"#,
+        id(description),
+        escape(description)
+    )
+    .unwrap();
+    writeln!(keylayout, "  <layouts>").unwrap();
+    writeln!(
+        keylayout,
+        r#"    <layout first="0" last="17" mapSet="characters" modifiers="modifiers"/>"#
+    )
+    .unwrap();
+    writeln!(keylayout, "  </layouts>").unwrap();
+    writeln!(
+        keylayout,
+        r#"  <modifierMap id="modifiers" defaultIndex="0">"#
+    )
+    .unwrap();
+    for (index, key_map) in key_maps.iter().enumerate() {
+        writeln!(keylayout, r#"    <keyMapSelect mapIndex="{}">"#, index).unwrap();
+        for modifiers in key_map.modifiers {
+            writeln!(keylayout, r#"      <modifier keys="{}"/>"#, modifiers).unwrap();
+        }
+
+        writeln!(keylayout, "    </keyMapSelect>").unwrap();
+    }
+
+    writeln!(keylayout, "  </modifierMap>").unwrap();
+    writeln!(keylayout, r#"  <keyMapSet id="characters">"#).unwrap();
+    for (index, key_map) in key_maps.iter().enumerate() {
+        writeln!(keylayout, r#"    <keyMap index="{}">"#, index).unwrap();
+        let mut keys = levels
+            .iter()
+            .zip(&key_codes)
+            .map(|(levels, &code)| (code, (key_map.level)(levels).clone()))
+            .collect::<Vec<_>>();
+        keys.push((SPACE_KEY_CODE, Key::Char(' ')));
+        keys.sort_by_key(|(code, _)| *code);
+        for (code, key) in keys {
+            match key {
+                Key::Char(c) if accented_chars.contains(&c) => writeln!(
+                    keylayout,
+                    r#"      <key code="{}" action="{}"/>"#,
+                    code,
+                    escape(&c.to_string())
+                ),
+                Key::Char(c) => writeln!(
+                    keylayout,
+                    r#"      <key code="{}" output="{}"/>"#,
+                    code,
+                    escape(&c.to_string())
+                ),
+                Key::Dead(accent) => writeln!(
+                    keylayout,
+                    r#"      <key code="{}" action="dead {}"/>"#,
+                    code,
+                    board::dead_key(accent).unwrap().name
+                ),
+                _ => continue,
+            }
+            .unwrap();
+        }
+
+        writeln!(keylayout, "    </keyMap>").unwrap();
+    }
+
+    writeln!(keylayout, "  </keyMapSet>").unwrap();
+    if !dead_keys.is_empty() {
+        writeln!(keylayout, "  <actions>").unwrap();
+        for dead_key in &dead_keys {
+            writeln!(keylayout, r#"    <action id="dead {}">"#, dead_key.name).unwrap();
+            writeln!(
+                keylayout,
+                r#"      <when state="none" next="{}"/>"#,
+                dead_key.name
+            )
+            .unwrap();
+            writeln!(keylayout, "    </action>").unwrap();
+        }
+
+        for c in &accented_chars {
+            let c_text = escape(&c.to_string());
+            writeln!(keylayout, r#"    <action id="{}">"#, c_text).unwrap();
+            writeln!(
+                keylayout,
+                r#"      <when state="none" output="{}"/>"#,
+                c_text
+            )
+            .unwrap();
+            for dead_key in &dead_keys {
+                if let Some((_, accented)) = dead_key.compositions().find(|(base, _)| base == c) {
+                    writeln!(
+                        keylayout,
+                        r#"      <when state="{}" output="{}"/>"#,
+                        dead_key.name, accented
+                    )
+                    .unwrap();
+                }
+            }
+
+            writeln!(keylayout, "    </action>").unwrap();
+        }
+
+        writeln!(keylayout, "  </actions>").unwrap();
+        writeln!(keylayout, "  <terminators>").unwrap();
+        for dead_key in &dead_keys {
+            writeln!(
+                keylayout,
+                r#"    <when state="{}" output="{}"/>"#,
+                dead_key.name, dead_key.accent
+            )
+            .unwrap();
+        }
+
+        writeln!(keylayout, "  </terminators>").unwrap();
+    }
+
+    writeln!(keylayout, "</keyboard>").unwrap();
+    Ok(keylayout)
+}
+
+// Key code of the key at a position of an ANSI or ISO board
+fn key_code(matrix_position: MatrixPosition) -> Option<u8> {
+    let (first_col, codes) = KEY_CODES.get(matrix_position.row as usize)?;
+    let col = matrix_position.col.checked_sub(*first_col)?;
+    codes.get(col as usize).copied()
+}
+
+// Layouts that aren't registered with Apple need a negative id, this one is derived from the
+// name so that exporting the same layout again replaces it
+fn id(description: &str) -> i32 {
+    let hash = description.bytes().fold(0u32, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as u32)
+    });
+    -((hash % 32_000) as i32) - 1
+}
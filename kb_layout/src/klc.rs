@@ -0,0 +1,277 @@
+use std::fmt::Write;
+
+use crate::board::{self, LEVEL_NAMES};
+use crate::{ExportError, Key, Keyboard, Layout, MatrixPosition};
+
+// Virtual keys of the US layout for the characters on it, with and without shift
+const VIRTUAL_KEYS: [(&str, &str); 11] = [
+    ("`~", "OEM_3"),
+    ("-_", "OEM_MINUS"),
+    ("=+", "OEM_PLUS"),
+    ("[{", "OEM_4"),
+    ("]}", "OEM_6"),
+    ("\\|", "OEM_5"),
+    (";:", "OEM_1"),
+    ("'\"", "OEM_7"),
+    (",<", "OEM_COMMA"),
+    (".>", "OEM_PERIOD"),
+    ("/?", "OEM_2"),
+];
+
+// Scan codes and names of the keys around the ones that type characters
+const KEY_NAMES: [(&str, &str); 10] = [
+    ("01", "Esc"),
+    ("0e", "Backspace"),
+    ("0f", "Tab"),
+    ("1c", "Enter"),
+    ("1d", "Ctrl"),
+    ("2a", "Shift"),
+    ("36", "\"Right Shift\""),
+    ("38", "Alt"),
+    ("39", "Space"),
+    ("3a", "\"Caps Lock\""),
+];
+const EXTENDED_KEY_NAMES: [(&str, &str); 5] = [
+    ("1c", "\"Num Enter\""),
+    ("1d", "\"Right Ctrl\""),
+    ("38", "\"Right Alt\""),
+    ("5b", "\"Left Windows\""),
+    ("5c", "\"Right Windows\""),
+];
+
+/// Writes the layout as a Microsoft Keyboard Layout Creator source file for a keyboard from
+/// `board::Board`, with columns for shift, control and, when the layout has an AltGr layer, for
+/// AltGr with and without shift. The levels come from `board::levels`, letters follow Caps Lock and
+/// their virtual keys move with them so shortcuts stay on the letters. `name` is the name of the
+/// DLL, shortened to 8 letters and digits. Write the file with `encode`
+pub fn source(
+    keyboard: &Keyboard,
+    layout: &Layout,
+    name: &str,
+    description: &str,
+) -> Result<String, ExportError> {
+    layout.check(keyboard)?;
+    let levels = board::levels(layout);
+    let altgr = levels.iter().any(|levels| levels[2] != Key::None);
+    let name = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .take(8)
+        .collect::<String>();
+    let description = description.replace('"', "'");
+
+    let mut source = String::new();
+    writeln!(source, "KBD\t{}\t\"{}\"", name, description).unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "COPYRIGHT\t\"\"").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "COMPANY\t\"\"").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "LOCALENAME\t\"en-US\"").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "LOCALEID\t\"00000409\"").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "VERSION\t1.0").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "SHIFTSTATE").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "0\t//Column 4").unwrap();
+    writeln!(source, "1\t//Column 5 : Shft").unwrap();
+    writeln!(source, "2\t//Column 6 :       Ctrl").unwrap();
+    if altgr {
+        writeln!(source, "6\t//Column 7 :       Ctrl Alt").unwrap();
+        writeln!(source, "7\t//Column 8 : Shft  Ctrl Alt").unwrap();
+    }
+
+    writeln!(source).unwrap();
+    writeln!(source, "LAYOUT\t\t;an extra '@' at the end is a dead key").unwrap();
+    writeln!(source).unwrap();
+    let shift_states = if altgr { "0\t1\t2\t6\t7" } else { "0\t1\t2" };
+    writeln!(source, "//SC\tVK_\t\tCap\t{}", shift_states).unwrap();
+    writeln!(
+        source,
+        "//--\t----\t\t----{}",
+        "\t----".repeat(if altgr { 5 } else { 3 })
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+
+    let mut dead_keys = Vec::new();
+    for i in keyboard.matrix_rows().into_iter().flatten() {
+        let matrix_position = keyboard.buttons[i].matrix_position;
+        let scan_code =
+            scan_code(matrix_position).ok_or(ExportError::UnsupportedButton { matrix_position })?;
+        let levels = &levels[i];
+        let mut cells = Vec::new();
+        for (level, key) in levels.iter().enumerate() {
+            if level == 2 {
+                cells.push("-1".to_string());
+            }
+
+            if level >= 2 && !altgr {
+                break;
+            }
+
+            cells.push(cell(key).ok_or_else(|| ExportError::UnsupportedKey {
+                layer: LEVEL_NAMES[level].to_string(),
+                key: key.clone(),
+            })?);
+            if let Key::Dead(accent) = key {
+                if !dead_keys.contains(accent) {
+                    dead_keys.push(*accent);
+                }
+            }
+        }
+
+        let caps = match &levels[..2] {
+            [Key::Char(base), Key::Char(shifted)] => {
+                base.is_alphabetic() && shifted.is_uppercase() && board::shifted(*base) == *shifted
+            }
+            _ => false,
+        };
+        writeln!(
+            source,
+            "{}\t{}\t\t{}\t{}\t// {}",
+            scan_code,
+            virtual_key(&levels[0], matrix_position),
+            caps as u8,
+            cells.join("\t"),
+            levels
+                .iter()
+                .filter(|key| **key != Key::None)
+                .map(Key::legend)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .unwrap();
+    }
+
+    let space = if altgr {
+        "0020\t0020\t0020\t-1\t-1"
+    } else {
+        "0020\t0020\t0020"
+    };
+    writeln!(source, "39\tSPACE\t\t0\t{}", space).unwrap();
+    writeln!(source).unwrap();
+
+    for accent in &dead_keys {
+        let dead_key = board::dead_key(*accent).unwrap();
+        writeln!(source).unwrap();
+        writeln!(source, "DEADKEY\t{:04x}", *accent as u32).unwrap();
+        writeln!(source).unwrap();
+        for (base, accented) in dead_key.compositions() {
+            writeln!(
+                source,
+                "{:04x}\t{:04x}\t// {} -> {}",
+                base as u32, accented as u32, base, accented
+            )
+            .unwrap();
+        }
+
+        writeln!(source, "0020\t{:04x}\t//   -> {}", *accent as u32, accent).unwrap();
+    }
+
+    writeln!(source).unwrap();
+    writeln!(source, "KEYNAME").unwrap();
+    writeln!(source).unwrap();
+    for (scan_code, name) in KEY_NAMES {
+        writeln!(source, "{}\t{}", scan_code, name).unwrap();
+    }
+
+    writeln!(source).unwrap();
+    writeln!(source, "KEYNAME_EXT").unwrap();
+    writeln!(source).unwrap();
+    for (scan_code, name) in EXTENDED_KEY_NAMES {
+        writeln!(source, "{}\t{}", scan_code, name).unwrap();
+    }
+
+    if !dead_keys.is_empty() {
+        writeln!(source).unwrap();
+        writeln!(source, "KEYNAME_DEAD").unwrap();
+        writeln!(source).unwrap();
+        for accent in &dead_keys {
+            let dead_key = board::dead_key(*accent).unwrap();
+            writeln!(
+                source,
+                "{:04x}\t\"{}\"",
+                *accent as u32,
+                dead_key.name.to_uppercase()
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(source).unwrap();
+    writeln!(source, "DESCRIPTIONS").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "0409\t{}", description).unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "LANGUAGENAMES").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "0409\tEnglish (United States)").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "ENDKBD").unwrap();
+    Ok(source.replace('\n', "\r\n"))
+}
+
+/// The bytes of a source file, Keyboard Layout Creator reads UTF-16 with a byte order mark
+pub fn encode(source: &str) -> Vec<u8> {
+    [0xfeff]
+        .into_iter()
+        .chain(source.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+// Scan code of the key at a position of an ANSI or ISO board
+fn scan_code(matrix_position: MatrixPosition) -> Option<String> {
+    let scan_code = match (matrix_position.row, matrix_position.col) {
+        (0, 0) => 0x29,
+        (1, 13) | (2, 12) => 0x2b,
+        (3, 0) => 0x56,
+        (0, col @ 1..=12) => 0x01 + col,
+        (1, col @ 1..=12) => 0x0f + col,
+        (2, col @ 1..=11) => 0x1d + col,
+        (3, col @ 1..=10) => 0x2b + col,
+        _ => return None,
+    };
+    Some(format!("{:02x}", scan_code))
+}
+
+// The virtual key of the US layout that types the character, or the one at the position when
+// the US layout doesn't have the character
+fn virtual_key(key: &Key, matrix_position: MatrixPosition) -> String {
+    let us_virtual_key = |c: char| {
+        if c.is_ascii_alphanumeric() {
+            Some(c.to_ascii_uppercase().to_string())
+        } else {
+            VIRTUAL_KEYS
+                .iter()
+                .find(|(chars, _)| chars.contains(c))
+                .map(|(_, virtual_key)| virtual_key.to_string())
+        }
+    };
+
+    // The key left of Z on ISO boards keeps its own virtual key unless it types a letter or a
+    // digit, the US layout types backslash on it as well
+    let iso_key = (matrix_position.row, matrix_position.col) == (3, 0);
+    match key {
+        Key::Char(c) if !iso_key || c.is_ascii_alphanumeric() => us_virtual_key(*c),
+        _ => None,
+    }
+    .or_else(|| match (matrix_position.row, matrix_position.col) {
+        (3, 0) => Some("OEM_102".to_string()),
+        _ => board::us_char(matrix_position).and_then(us_virtual_key),
+    })
+    .unwrap_or_else(|| "OEM_8".to_string())
+}
+
+fn cell(key: &Key) -> Option<String> {
+    match key {
+        Key::None => Some("-1".to_string()),
+        Key::Char(c) if c.is_ascii_alphanumeric() => Some(c.to_string()),
+        Key::Char(c) if c.len_utf16() == 1 => Some(format!("{:04x}", *c as u32)),
+        Key::Dead(accent) => board::dead_key(*accent).map(|_| format!("{:04x}@", *accent as u32)),
+        _ => None,
+    }
+}
@@ -0,0 +1,384 @@
+use std::fmt;
+
+use crate::{Keyboard, MatrixPosition};
+
+/// What the buttons of a keyboard do, layer by layer. The first layer is the base layer
+pub struct Layout {
+    pub layers: Vec<Layer>,
+}
+
+/// The keys of one layer, in the same order as the buttons of the keyboard
+pub struct Layer {
+    pub name: String,
+    pub keys: Vec<Key>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Key {
+    /// The button does nothing on this layer
+    None,
+    /// The button does what it does on the layer below
+    Transparent,
+    Char(char),
+    /// Puts the accent on the character typed next, see `board::DEAD_KEYS` for the accents
+    Dead(char),
+    Named(NamedKey),
+    Modifier(Modifier),
+    /// Switches to the layer while held
+    Momentary(usize),
+    /// Switches to the layer until pressed again
+    Toggle(usize),
+    /// Switches to the layer while held, types the key when tapped
+    LayerTap {
+        layer: usize,
+        tap: Box<Key>,
+    },
+    /// Holds the modifier while held, types the key when tapped, like home row mods
+    ModTap {
+        modifier: Modifier,
+        tap: Box<Key>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamedKey {
+    Space,
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Escape,
+    CapsLock,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modifier {
+    LeftShift,
+    LeftControl,
+    LeftAlt,
+    LeftGui,
+    RightShift,
+    RightControl,
+    /// AltGr on most layouts
+    RightAlt,
+    RightGui,
+}
+
+impl Key {
+    /// Short label to print on the key cap
+    pub fn legend(&self) -> String {
+        match self {
+            Self::None => String::new(),
+            Self::Transparent => "▽".to_string(),
+            Self::Char(c) | Self::Dead(c) => c.to_string(),
+            Self::Named(named_key) => named_key.legend().to_string(),
+            Self::Modifier(modifier) => modifier.legend().to_string(),
+            Self::Momentary(layer) => format!("MO {}", layer),
+            Self::Toggle(layer) => format!("TG {}", layer),
+            Self::LayerTap { tap, .. } | Self::ModTap { tap, .. } => tap.legend(),
+        }
+    }
+}
+
+impl Key {
+    // Named keys, modifiers and printable ASCII characters, to look keys up by what a format
+    // calls them. The space bar comes before the space character
+    pub(crate) fn basic_keys() -> impl Iterator<Item = Key> {
+        NamedKey::ALL
+            .into_iter()
+            .map(Key::Named)
+            .chain(Modifier::ALL.into_iter().map(Key::Modifier))
+            .chain((' '..='~').map(Key::Char))
+    }
+}
+
+impl NamedKey {
+    pub const ALL: [NamedKey; 15] = [
+        Self::Space,
+        Self::Enter,
+        Self::Backspace,
+        Self::Delete,
+        Self::Tab,
+        Self::Escape,
+        Self::CapsLock,
+        Self::Left,
+        Self::Right,
+        Self::Up,
+        Self::Down,
+        Self::Home,
+        Self::End,
+        Self::PageUp,
+        Self::PageDown,
+    ];
+
+    pub fn legend(self) -> &'static str {
+        match self {
+            Self::Space => "␣",
+            Self::Enter => "⏎",
+            Self::Backspace => "⌫",
+            Self::Delete => "⌦",
+            Self::Tab => "⇥",
+            Self::Escape => "Esc",
+            Self::CapsLock => "Caps",
+            Self::Left => "←",
+            Self::Right => "→",
+            Self::Up => "↑",
+            Self::Down => "↓",
+            Self::Home => "Home",
+            Self::End => "End",
+            Self::PageUp => "PgUp",
+            Self::PageDown => "PgDn",
+        }
+    }
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 8] = [
+        Self::LeftShift,
+        Self::LeftControl,
+        Self::LeftAlt,
+        Self::LeftGui,
+        Self::RightShift,
+        Self::RightControl,
+        Self::RightAlt,
+        Self::RightGui,
+    ];
+
+    pub fn legend(self) -> &'static str {
+        match self {
+            Self::LeftShift | Self::RightShift => "Shift",
+            Self::LeftControl | Self::RightControl => "Ctrl",
+            Self::LeftAlt => "Alt",
+            Self::RightAlt => "AltGr",
+            Self::LeftGui | Self::RightGui => "Gui",
+        }
+    }
+}
+
+/// Why a layout could not be written in a format
+#[derive(Debug)]
+pub enum ExportError {
+    /// The layer doesn't have a key for every button of the keyboard
+    KeyCount {
+        layer: String,
+        keys: usize,
+        buttons: usize,
+    },
+    /// The format has no way to express the key
+    UnsupportedKey { layer: String, key: Key },
+    /// The format has no key at the matrix position of a button
+    UnsupportedButton { matrix_position: MatrixPosition },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::KeyCount {
+                layer,
+                keys,
+                buttons,
+            } => write!(
+                f,
+                "layer {} has {} keys but the keyboard has {} buttons",
+                layer, keys, buttons
+            ),
+            Self::UnsupportedKey { layer, key } => {
+                write!(f, "{:?} on layer {} is not supported", key, layer)
+            }
+            Self::UnsupportedButton { matrix_position } => write!(
+                f,
+                "there is no key at column {} of row {}",
+                matrix_position.col, matrix_position.row
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// Why a layout could not be read from a file
+#[derive(Debug)]
+pub enum ImportError {
+    /// The file is not in the format
+    Syntax(String),
+    /// The layout model has no key for what the file calls `key`
+    UnknownKey { layer: String, key: String },
+    /// The layer doesn't have a key for every button of the keyboard
+    KeyCount {
+        layer: String,
+        keys: usize,
+        buttons: usize,
+    },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Syntax(message) => write!(f, "{}", message),
+            Self::UnknownKey { layer, key } => write!(f, "unknown key {} on layer {}", key, layer),
+            Self::KeyCount {
+                layer,
+                keys,
+                buttons,
+            } => write!(
+                f,
+                "layer {} has {} keys but the keyboard has {} buttons",
+                layer, keys, buttons
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl Layer {
+    /// A layer with the keys in matrix order placed on the buttons of the keyboard
+    pub fn from_matrix_order(
+        keyboard: &Keyboard,
+        name: String,
+        keys: Vec<Key>,
+    ) -> Result<Layer, ImportError> {
+        if keys.len() != keyboard.buttons.len() {
+            return Err(ImportError::KeyCount {
+                layer: name,
+                keys: keys.len(),
+                buttons: keyboard.buttons.len(),
+            });
+        }
+
+        let mut layer_keys = vec![Key::None; keys.len()];
+        for (i, key) in keyboard.matrix_rows().into_iter().flatten().zip(keys) {
+            layer_keys[i] = key;
+        }
+
+        Ok(Layer {
+            name,
+            keys: layer_keys,
+        })
+    }
+
+    // What `name` calls each key, row by row in the order of `rows`, or an error for the first
+    // key it has no name for
+    pub(crate) fn key_rows(
+        &self,
+        rows: &[Vec<usize>],
+        mut name: impl FnMut(&Key) -> Option<String>,
+    ) -> Result<Vec<Vec<String>>, ExportError> {
+        rows.iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&i| {
+                        name(&self.keys[i]).ok_or_else(|| ExportError::UnsupportedKey {
+                            layer: self.name.clone(),
+                            key: self.keys[i].clone(),
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl Layout {
+    pub(crate) fn layer_identifiers(&self) -> Vec<String> {
+        self.layers
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| layer_identifier(&layer.name, i))
+            .collect()
+    }
+
+    /// Checks that every layer has a key for every button of the keyboard
+    pub fn check(&self, keyboard: &Keyboard) -> Result<(), ExportError> {
+        match self
+            .layers
+            .iter()
+            .find(|layer| layer.keys.len() != keyboard.buttons.len())
+        {
+            Some(layer) => Err(ExportError::KeyCount {
+                layer: layer.name.clone(),
+                keys: layer.keys.len(),
+                buttons: keyboard.buttons.len(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+// An uppercase C identifier for the layer, used to refer to it in keymaps
+pub(crate) fn layer_identifier(name: &str, i: usize) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("LAYER_{}{}", i, name)
+    } else {
+        name
+    }
+}
+
+// Splits the arguments of a function like macro at the commas outside of parentheses
+pub(crate) fn split_arguments(arguments: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in arguments.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(arguments[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    let last = arguments[start..].trim();
+    if !last.is_empty() || !parts.is_empty() {
+        parts.push(last);
+    }
+
+    parts
+}
+
+// Splits a call like LT(1, KC_A) into its name and arguments
+pub(crate) fn split_call(call: &str) -> Option<(&str, Vec<&str>)> {
+    let (name, rest) = call.split_once('(')?;
+    let arguments = rest.strip_suffix(')')?;
+    Some((name.trim(), split_arguments(arguments)))
+}
+
+// Removes // and /* */ comments from C like source
+pub(crate) fn strip_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find("//").into_iter().chain(rest.find("/*")).min() {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else {
+            rest.find("*/").map_or(rest.len(), |end| end + 2)
+        };
+        rest = &rest[end..];
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
@@ -1,7 +1,42 @@
+pub mod board;
+pub mod kanata;
+pub mod keylayout;
+pub mod klc;
+mod layout;
+pub mod qmk;
+pub mod svg;
+pub mod xkb;
+pub mod zmk;
+
+pub use layout::{ExportError, ImportError, Key, Layer, Layout, Modifier, NamedKey};
+
 pub struct Keyboard {
     pub buttons: Vec<Button>,
 }
 
+impl Keyboard {
+    /// Indices of the buttons grouped by matrix row and sorted by column within each row, the
+    /// order firmware keymaps list them in
+    pub fn matrix_rows(&self) -> Vec<Vec<usize>> {
+        let mut order = (0..self.buttons.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| {
+            let matrix_position = self.buttons[i].matrix_position;
+            (matrix_position.row, matrix_position.col)
+        });
+
+        let mut rows: Vec<Vec<usize>> = Vec::new();
+        for i in order {
+            let row = self.buttons[i].matrix_position.row;
+            match rows.last_mut() {
+                Some(last) if self.buttons[last[0]].matrix_position.row == row => last.push(i),
+                _ => rows.push(vec![i]),
+            }
+        }
+
+        rows
+    }
+}
+
 pub struct Button {
     pub hand: Hand,
     pub finger: Finger,
@@ -11,11 +46,13 @@ pub struct Button {
     pub cost: f64,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Hand {
     Left,
     Right,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Finger {
     Thumb,
     Pointer,
@@ -24,11 +61,13 @@ pub enum Finger {
     Pinky,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Position {
     pub x: f64,
     pub y: f64,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MatrixPosition {
     pub col: u8,
     pub row: u8,
@@ -0,0 +1,429 @@
+use std::fmt::Write;
+
+use crate::board::shifted;
+use crate::layout::{split_arguments, split_call, strip_comments};
+use crate::{ExportError, ImportError, Key, Keyboard, Layer, Layout, Modifier, NamedKey};
+
+// Characters typed without shift and their basic keycodes
+const UNSHIFTED: [(char, &str); 12] = [
+    (' ', "KC_SPC"),
+    (',', "KC_COMM"),
+    ('.', "KC_DOT"),
+    ('/', "KC_SLSH"),
+    (';', "KC_SCLN"),
+    ('\'', "KC_QUOT"),
+    ('[', "KC_LBRC"),
+    (']', "KC_RBRC"),
+    ('\\', "KC_BSLS"),
+    ('-', "KC_MINS"),
+    ('=', "KC_EQL"),
+    ('`', "KC_GRV"),
+];
+
+// Characters typed with shift, these keycodes can't be the tap of a mod-tap or layer-tap
+const SHIFTED: [(char, &str); 21] = [
+    ('?', "KC_QUES"),
+    ('!', "KC_EXLM"),
+    ('@', "KC_AT"),
+    ('#', "KC_HASH"),
+    ('$', "KC_DLR"),
+    ('%', "KC_PERC"),
+    ('^', "KC_CIRC"),
+    ('&', "KC_AMPR"),
+    ('*', "KC_ASTR"),
+    ('(', "KC_LPRN"),
+    (')', "KC_RPRN"),
+    ('_', "KC_UNDS"),
+    ('+', "KC_PLUS"),
+    ('{', "KC_LCBR"),
+    ('}', "KC_RCBR"),
+    ('|', "KC_PIPE"),
+    (':', "KC_COLN"),
+    ('"', "KC_DQUO"),
+    ('<', "KC_LABK"),
+    ('>', "KC_RABK"),
+    ('~', "KC_TILD"),
+];
+
+// Long names of keycodes and the short ones the exporter writes
+const KEYCODE_ALIASES: [(&str, &str); 40] = [
+    ("KC_TRANSPARENT", "KC_TRNS"),
+    ("KC_ENTER", "KC_ENT"),
+    ("KC_SPACE", "KC_SPC"),
+    ("KC_BSPACE", "KC_BSPC"),
+    ("KC_BACKSPACE", "KC_BSPC"),
+    ("KC_DELETE", "KC_DEL"),
+    ("KC_ESCAPE", "KC_ESC"),
+    ("KC_CAPSLOCK", "KC_CAPS"),
+    ("KC_CAPS_LOCK", "KC_CAPS"),
+    ("KC_RIGHT", "KC_RGHT"),
+    ("KC_PGDOWN", "KC_PGDN"),
+    ("KC_PAGE_UP", "KC_PGUP"),
+    ("KC_PAGE_DOWN", "KC_PGDN"),
+    ("KC_COMMA", "KC_COMM"),
+    ("KC_SLASH", "KC_SLSH"),
+    ("KC_SCOLON", "KC_SCLN"),
+    ("KC_SEMICOLON", "KC_SCLN"),
+    ("KC_QUOTE", "KC_QUOT"),
+    ("KC_LBRACKET", "KC_LBRC"),
+    ("KC_LEFT_BRACKET", "KC_LBRC"),
+    ("KC_RBRACKET", "KC_RBRC"),
+    ("KC_RIGHT_BRACKET", "KC_RBRC"),
+    ("KC_BSLASH", "KC_BSLS"),
+    ("KC_BACKSLASH", "KC_BSLS"),
+    ("KC_MINUS", "KC_MINS"),
+    ("KC_EQUAL", "KC_EQL"),
+    ("KC_GRAVE", "KC_GRV"),
+    ("KC_LSHIFT", "KC_LSFT"),
+    ("KC_LEFT_SHIFT", "KC_LSFT"),
+    ("KC_RSHIFT", "KC_RSFT"),
+    ("KC_RIGHT_SHIFT", "KC_RSFT"),
+    ("KC_LCTRL", "KC_LCTL"),
+    ("KC_LEFT_CTRL", "KC_LCTL"),
+    ("KC_RCTRL", "KC_RCTL"),
+    ("KC_RIGHT_CTRL", "KC_RCTL"),
+    ("KC_LEFT_ALT", "KC_LALT"),
+    ("KC_RIGHT_ALT", "KC_RALT"),
+    ("KC_ALGR", "KC_RALT"),
+    ("KC_LEFT_GUI", "KC_LGUI"),
+    ("KC_RIGHT_GUI", "KC_RGUI"),
+];
+
+/// Writes the layout as the keymap.c of a QMK keyboard, with a `layout_macro(...)` per layer
+/// listing the keys in matrix order, one line per matrix row. Characters without a keycode are
+/// written as `UC(...)`, which needs UNICODE_ENABLE in rules.mk
+pub fn keymap_c(
+    keyboard: &Keyboard,
+    layout: &Layout,
+    layout_macro: &str,
+) -> Result<String, ExportError> {
+    layout.check(keyboard)?;
+    let layer_names = layout.layer_identifiers();
+
+    let mut keymap = String::new();
+    writeln!(keymap, "#include QMK_KEYBOARD_H").unwrap();
+    writeln!(keymap).unwrap();
+    writeln!(keymap, "enum layers {{").unwrap();
+    for name in &layer_names {
+        writeln!(keymap, "    {},", name).unwrap();
+    }
+
+    writeln!(keymap, "}};").unwrap();
+    writeln!(keymap).unwrap();
+    writeln!(
+        keymap,
+        "const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {{"
+    )
+    .unwrap();
+    let rows = keyboard.matrix_rows();
+    for (layer, name) in layout.layers.iter().zip(&layer_names) {
+        let keycode_rows = layer.key_rows(&rows, |key| keycode(key, &layer_names))?;
+        let width = keycode_rows
+            .iter()
+            .flatten()
+            .map(String::len)
+            .max()
+            .unwrap_or(0);
+
+        writeln!(keymap, "    [{}] = {}(", name, layout_macro).unwrap();
+        for (row_idx, keycodes) in keycode_rows.iter().enumerate() {
+            let last_row = row_idx + 1 == keycode_rows.len();
+            let mut line = String::new();
+            for (i, keycode) in keycodes.iter().enumerate() {
+                if last_row && i + 1 == keycodes.len() {
+                    line.push_str(keycode);
+                } else {
+                    write!(
+                        line,
+                        "{:<width$}",
+                        format!("{},", keycode),
+                        width = width + 2
+                    )
+                    .unwrap();
+                }
+            }
+
+            writeln!(keymap, "        {}", line.trim_end()).unwrap();
+        }
+
+        writeln!(keymap, "    ),").unwrap();
+    }
+
+    writeln!(keymap, "}};").unwrap();
+    Ok(keymap)
+}
+
+fn keycode(key: &Key, layer_names: &[String]) -> Option<String> {
+    let layer_ref = |layer: usize| layer_names.get(layer).cloned();
+    match key {
+        Key::None => Some("KC_NO".to_string()),
+        Key::Transparent => Some("KC_TRNS".to_string()),
+        Key::Char(c) => basic_keycode(key)
+            .map(str::to_string)
+            .or_else(|| {
+                SHIFTED
+                    .iter()
+                    .find(|(shifted, _)| shifted == c)
+                    .map(|(_, keycode)| keycode.to_string())
+            })
+            .or_else(|| c.is_ascii_uppercase().then(|| format!("S(KC_{})", c)))
+            .or_else(|| (!c.is_ascii()).then(|| format!("UC(0x{:04X})", *c as u32))),
+        Key::Named(_) | Key::Modifier(_) => basic_keycode(key).map(str::to_string),
+        Key::Dead(_) => None,
+        Key::Momentary(layer) => Some(format!("MO({})", layer_ref(*layer)?)),
+        Key::Toggle(layer) => Some(format!("TG({})", layer_ref(*layer)?)),
+        Key::LayerTap { layer, tap } => Some(format!(
+            "LT({}, {})",
+            layer_ref(*layer)?,
+            basic_keycode(tap)?
+        )),
+        Key::ModTap { modifier, tap } => Some(format!(
+            "MT({}, {})",
+            mod_bit(*modifier),
+            basic_keycode(tap)?
+        )),
+    }
+}
+
+// Keycodes that can be the tap of a mod-tap or layer-tap
+fn basic_keycode(key: &Key) -> Option<&'static str> {
+    const LETTERS: [&str; 26] = [
+        "KC_A", "KC_B", "KC_C", "KC_D", "KC_E", "KC_F", "KC_G", "KC_H", "KC_I", "KC_J", "KC_K",
+        "KC_L", "KC_M", "KC_N", "KC_O", "KC_P", "KC_Q", "KC_R", "KC_S", "KC_T", "KC_U", "KC_V",
+        "KC_W", "KC_X", "KC_Y", "KC_Z",
+    ];
+    const DIGITS: [&str; 10] = [
+        "KC_0", "KC_1", "KC_2", "KC_3", "KC_4", "KC_5", "KC_6", "KC_7", "KC_8", "KC_9",
+    ];
+
+    match key {
+        Key::Char(c @ 'a'..='z') => Some(LETTERS[(*c as u8 - b'a') as usize]),
+        Key::Char(c @ '0'..='9') => Some(DIGITS[(*c as u8 - b'0') as usize]),
+        Key::Char(c) => UNSHIFTED
+            .iter()
+            .find(|(unshifted, _)| unshifted == c)
+            .map(|(_, keycode)| *keycode),
+        Key::Named(named_key) => Some(match named_key {
+            NamedKey::Space => "KC_SPC",
+            NamedKey::Enter => "KC_ENT",
+            NamedKey::Backspace => "KC_BSPC",
+            NamedKey::Delete => "KC_DEL",
+            NamedKey::Tab => "KC_TAB",
+            NamedKey::Escape => "KC_ESC",
+            NamedKey::CapsLock => "KC_CAPS",
+            NamedKey::Left => "KC_LEFT",
+            NamedKey::Right => "KC_RGHT",
+            NamedKey::Up => "KC_UP",
+            NamedKey::Down => "KC_DOWN",
+            NamedKey::Home => "KC_HOME",
+            NamedKey::End => "KC_END",
+            NamedKey::PageUp => "KC_PGUP",
+            NamedKey::PageDown => "KC_PGDN",
+        }),
+        Key::Modifier(modifier) => Some(match modifier {
+            Modifier::LeftShift => "KC_LSFT",
+            Modifier::LeftControl => "KC_LCTL",
+            Modifier::LeftAlt => "KC_LALT",
+            Modifier::LeftGui => "KC_LGUI",
+            Modifier::RightShift => "KC_RSFT",
+            Modifier::RightControl => "KC_RCTL",
+            Modifier::RightAlt => "KC_RALT",
+            Modifier::RightGui => "KC_RGUI",
+        }),
+        _ => None,
+    }
+}
+
+fn mod_bit(modifier: Modifier) -> &'static str {
+    match modifier {
+        Modifier::LeftShift => "MOD_LSFT",
+        Modifier::LeftControl => "MOD_LCTL",
+        Modifier::LeftAlt => "MOD_LALT",
+        Modifier::LeftGui => "MOD_LGUI",
+        Modifier::RightShift => "MOD_RSFT",
+        Modifier::RightControl => "MOD_RCTL",
+        Modifier::RightAlt => "MOD_RALT",
+        Modifier::RightGui => "MOD_RGUI",
+    }
+}
+
+/// Reads the keymap.json of the QMK configurator. The keycodes of each layer are in the order of
+/// the layout macro, which has to be the matrix order of `keyboard`
+pub fn parse_keymap_json(keyboard: &Keyboard, json: &str) -> Result<Layout, ImportError> {
+    let keymap: serde_json::Value =
+        serde_json::from_str(json).map_err(|err| ImportError::Syntax(err.to_string()))?;
+    let layers = keymap["layers"]
+        .as_array()
+        .ok_or_else(|| ImportError::Syntax("the keymap has no layers".to_string()))?
+        .iter()
+        .enumerate()
+        .map(|(i, layer)| {
+            let keycodes = layer
+                .as_array()
+                .and_then(|keycodes| {
+                    keycodes
+                        .iter()
+                        .map(|keycode| keycode.as_str())
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| {
+                    ImportError::Syntax(format!("layer {} is not a list of keycodes", i))
+                })?;
+            Ok((i.to_string(), keycodes))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    parse_layers(keyboard, &layers)
+}
+
+/// Reads the keymaps array of a keymap.c, with the keycodes of each layer in the order of the
+/// layout macro, which has to be the matrix order of `keyboard`. Layers are referred to by number
+/// or by the names of their entries in the array
+pub fn parse_keymap_c(keyboard: &Keyboard, source: &str) -> Result<Layout, ImportError> {
+    let source = strip_comments(source);
+    let syntax = |message: &str| ImportError::Syntax(message.to_string());
+    let start = source
+        .find("keymaps")
+        .ok_or_else(|| syntax("there is no keymaps array"))?;
+    let mut rest = &source[start..];
+    rest = &rest[rest
+        .find('{')
+        .ok_or_else(|| syntax("the keymaps array has no body"))?
+        + 1..];
+
+    // Entries look like [NAME] = LAYOUT(...), separated by commas
+    let mut layers = Vec::new();
+    while let Some(entry) = rest.trim_start().strip_prefix('[') {
+        let (name, entry) = entry
+            .split_once(']')
+            .ok_or_else(|| syntax("a layer name is missing its ]"))?;
+        let entry = entry
+            .trim_start()
+            .strip_prefix('=')
+            .ok_or_else(|| syntax("a layer name is not followed by ="))?;
+        let open = entry
+            .find('(')
+            .ok_or_else(|| syntax("a layer is not a layout macro"))?;
+        let mut depth = 0;
+        let close = entry[open..]
+            .char_indices()
+            .find_map(|(i, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(open + i)
+            })
+            .ok_or_else(|| syntax("a layout macro is missing its )"))?;
+        layers.push((
+            name.trim().to_string(),
+            split_arguments(&entry[open + 1..close]),
+        ));
+        rest = entry[close + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+
+    if layers.is_empty() {
+        return Err(syntax("the keymaps array has no layers"));
+    }
+
+    parse_layers(keyboard, &layers)
+}
+
+fn parse_layers(
+    keyboard: &Keyboard,
+    layers: &[(String, Vec<&str>)],
+) -> Result<Layout, ImportError> {
+    let layer_names = layers
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    let layers = layers
+        .iter()
+        .map(|(name, keycodes)| {
+            let keys = keycodes
+                .iter()
+                .map(|keycode| {
+                    parse_keycode(keycode, &layer_names).ok_or_else(|| ImportError::UnknownKey {
+                        layer: name.clone(),
+                        key: keycode.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Layer::from_matrix_order(keyboard, name.clone(), keys)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Layout { layers })
+}
+
+fn parse_keycode(token: &str, layer_names: &[String]) -> Option<Key> {
+    let token = token.trim();
+    let token = KEYCODE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == token)
+        .map_or(token, |(_, keycode)| keycode);
+    match token {
+        "XXXXXXX" => return Some(Key::None),
+        "_______" => return Some(Key::Transparent),
+        _ => {}
+    }
+
+    let layer = |name: &str| {
+        name.parse()
+            .ok()
+            .or_else(|| layer_names.iter().position(|layer_name| layer_name == name))
+    };
+    let modifier = |name: &str| {
+        let name = match name {
+            "MOD_LCMD" | "MOD_LOPT" => name.replace("CMD", "GUI").replace("OPT", "ALT"),
+            _ => name.to_string(),
+        };
+        Modifier::ALL
+            .into_iter()
+            .find(|&modifier| mod_bit(modifier) == name)
+    };
+    if let Some((name, arguments)) = split_call(token) {
+        let tap = |tap: &str| parse_keycode(tap, layer_names).map(Box::new);
+        return match (name, &arguments[..]) {
+            ("MO", [l]) => Some(Key::Momentary(layer(l)?)),
+            ("TG", [l]) => Some(Key::Toggle(layer(l)?)),
+            ("LT", [l, tap_keycode]) => Some(Key::LayerTap {
+                layer: layer(l)?,
+                tap: tap(tap_keycode)?,
+            }),
+            ("MT", [modifier_name, tap_keycode]) => Some(Key::ModTap {
+                modifier: modifier(modifier_name)?,
+                tap: tap(tap_keycode)?,
+            }),
+            ("S" | "LSFT", [keycode]) => match parse_keycode(keycode, layer_names)? {
+                Key::Char(c) => Some(Key::Char(shifted(c))),
+                _ => None,
+            },
+            ("UC", [code]) => {
+                let code = code
+                    .strip_prefix("0x")
+                    .or_else(|| code.strip_prefix("0X"))?;
+                char::from_u32(u32::from_str_radix(code, 16).ok()?).map(Key::Char)
+            }
+            // Mod-tap shorthands like LGUI_T(KC_A), with or without the side
+            (name, [tap_keycode]) => {
+                let name = name.strip_suffix("_T")?;
+                let name = if name.starts_with(['L', 'R']) {
+                    name.to_string()
+                } else {
+                    format!("L{}", name)
+                };
+                Some(Key::ModTap {
+                    modifier: modifier(&format!("MOD_{}", name))?,
+                    tap: tap(tap_keycode)?,
+                })
+            }
+            _ => None,
+        };
+    }
+
+    Key::basic_keys()
+        .chain([Key::None, Key::Transparent])
+        .find(|key| keycode(key, layer_names).as_deref() == Some(token))
+}
@@ -0,0 +1,262 @@
+use std::fmt::Write;
+
+use crate::{Finger, Keyboard, Layer};
+
+// Size of a 1u key, the same as in the web editor
+const PX_PER_UNIT: f64 = 54.0;
+const KEY_GAP: f64 = 4.0;
+const MARGIN: f64 = 10.0;
+const SCALE_HEIGHT: f64 = 40.0;
+
+const FINGERS: [(Finger, &str, Rgb); 5] = [
+    (Finger::Thumb, "thumb", Rgb(0xcc, 0xcc, 0xcc)),
+    (Finger::Pointer, "pointer", Rgb(0x64, 0x95, 0xed)),
+    (Finger::Middle, "middle", Rgb(0x8f, 0xbc, 0x8f)),
+    (Finger::Ring, "ring", Rgb(0xf0, 0xe6, 0x8c)),
+    (Finger::Pinky, "pinky", Rgb(0xff, 0x7f, 0x50)),
+];
+const FREQUENCY_SCALE: [Rgb; 2] = [Rgb(0xf7, 0xfb, 0xff), Rgb(0x08, 0x51, 0x9c)];
+const COST_SCALE: [Rgb; 3] = [
+    Rgb(0x1a, 0x98, 0x50),
+    Rgb(0xfe, 0xe0, 0x8b),
+    Rgb(0xd7, 0x30, 0x27),
+];
+
+/// How the key caps are filled
+pub enum Coloring<'a> {
+    /// One color per finger, the same on both hands
+    Finger,
+    /// How often each button is pressed, in the order of the keyboard's buttons. Buttons without a
+    /// value are white
+    Frequency(&'a [Option<f64>]),
+    /// What each button adds to the cost of the layout, in the order of the keyboard's buttons
+    Cost(&'a [Option<f64>]),
+}
+
+#[derive(Clone, Copy)]
+struct Rgb(u8, u8, u8);
+
+impl Rgb {
+    fn lerp(self, other: Rgb, t: f64) -> Rgb {
+        let channel =
+            |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+        Rgb(
+            channel(self.0, other.0),
+            channel(self.1, other.1),
+            channel(self.2, other.2),
+        )
+    }
+
+    // Position along a scale of evenly spaced colors, t goes from 0.0 to 1.0
+    fn on_scale(scale: &[Rgb], t: f64) -> Rgb {
+        let t = t.clamp(0.0, 1.0) * (scale.len() - 1) as f64;
+        let i = (t.floor() as usize).min(scale.len() - 2);
+        scale[i].lerp(scale[i + 1], t - i as f64)
+    }
+
+    fn is_dark(self) -> bool {
+        let luma = 0.299 * self.0 as f64 + 0.587 * self.1 as f64 + 0.114 * self.2 as f64;
+        luma < 128.0
+    }
+}
+
+impl std::fmt::Display for Rgb {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+/// Draws the keyboard with every button at its position, labeled with its key on the layer and
+/// colored according to `coloring`, with the color scale below the keyboard
+pub fn render(keyboard: &Keyboard, layer: &Layer, coloring: &Coloring) -> String {
+    let (min_x, min_y, max_x, max_y) = keyboard.buttons.iter().fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(min_x, min_y, max_x, max_y), button| {
+            (
+                min_x.min(button.position.x),
+                min_y.min(button.position.y),
+                max_x.max(button.position.x + 1.0),
+                max_y.max(button.position.y + 1.0),
+            )
+        },
+    );
+    let keyboard_width = (max_x - min_x) * PX_PER_UNIT;
+    let keyboard_height = (max_y - min_y) * PX_PER_UNIT;
+    let width = keyboard_width + 2.0 * MARGIN;
+    let height = keyboard_height + SCALE_HEIGHT + 2.0 * MARGIN;
+
+    let (scale, values) = match coloring {
+        Coloring::Finger => (&[][..], &[][..]),
+        Coloring::Frequency(values) => (&FREQUENCY_SCALE[..], *values),
+        Coloring::Cost(values) => (&COST_SCALE[..], *values),
+    };
+    let min_value = values
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    let max_value = values
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    let value_color = |value: f64| {
+        let range = max_value - min_value;
+        let t = if range > 0.0 {
+            (value - min_value) / range
+        } else {
+            0.0
+        };
+        Rgb::on_scale(scale, t)
+    };
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w:.0}" height="{h:.0}" viewBox="0 0 {w:.0} {h:.0}" font-family="sans-serif">"#,
+        w = width,
+        h = height
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"<rect width="{:.0}" height="{:.0}" fill="white"/>"#,
+        width, height
+    )
+    .unwrap();
+
+    for (i, button) in keyboard.buttons.iter().enumerate() {
+        let fill = match coloring {
+            Coloring::Finger => finger_color(button.finger),
+            Coloring::Frequency(_) | Coloring::Cost(_) => values
+                .get(i)
+                .copied()
+                .flatten()
+                .map_or(Rgb(0xff, 0xff, 0xff), value_color),
+        };
+        let x = MARGIN + (button.position.x - min_x) * PX_PER_UNIT + KEY_GAP / 2.0;
+        let y = MARGIN + (button.position.y - min_y) * PX_PER_UNIT + KEY_GAP / 2.0;
+        let size = PX_PER_UNIT - KEY_GAP;
+        writeln!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" rx="5" fill="{}" stroke="black"/>"#,
+            x, y, size, size, fill
+        )
+        .unwrap();
+        let legend = layer
+            .keys
+            .get(i)
+            .map(|key| key.legend())
+            .unwrap_or_default();
+        if !legend.is_empty() {
+            writeln!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="middle" dominant-baseline="central" font-size="{}" fill="{}">{}</text>"#,
+                x + size / 2.0,
+                y + size / 2.0,
+                if legend.chars().count() > 2 { 11 } else { 20 },
+                if fill.is_dark() { "white" } else { "black" },
+                escape(&legend)
+            )
+            .unwrap();
+        }
+    }
+
+    let scale_y = MARGIN + keyboard_height + SCALE_HEIGHT / 4.0;
+    match coloring {
+        Coloring::Finger => {
+            let swatch_width = keyboard_width / FINGERS.len() as f64;
+            for (i, (_, name, color)) in FINGERS.iter().enumerate() {
+                let x = MARGIN + i as f64 * swatch_width;
+                writeln!(
+                    svg,
+                    r#"<rect x="{:.1}" y="{:.1}" width="12" height="12" fill="{}" stroke="black"/>"#,
+                    x, scale_y, color
+                )
+                .unwrap();
+                writeln!(
+                    svg,
+                    r#"<text x="{:.1}" y="{:.1}" font-size="12" dominant-baseline="central">{}</text>"#,
+                    x + 16.0,
+                    scale_y + 6.0,
+                    name
+                )
+                .unwrap();
+            }
+        }
+        Coloring::Frequency(_) | Coloring::Cost(_) => {
+            writeln!(svg, r#"<defs><linearGradient id="scale">"#).unwrap();
+            for (i, color) in scale.iter().enumerate() {
+                writeln!(
+                    svg,
+                    r#"<stop offset="{:.2}" stop-color="{}"/>"#,
+                    i as f64 / (scale.len() - 1) as f64,
+                    color
+                )
+                .unwrap();
+            }
+
+            writeln!(svg, "</linearGradient></defs>").unwrap();
+            let label_width = 60.0;
+            writeln!(
+                svg,
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="12" fill="url(#scale)" stroke="black"/>"#,
+                MARGIN + label_width,
+                scale_y,
+                (keyboard_width - 2.0 * label_width).max(0.0)
+            )
+            .unwrap();
+            for (x, anchor, value) in [
+                (MARGIN + label_width - 4.0, "end", min_value),
+                (
+                    MARGIN + keyboard_width - label_width + 4.0,
+                    "start",
+                    max_value,
+                ),
+            ] {
+                writeln!(
+                    svg,
+                    r#"<text x="{:.1}" y="{:.1}" font-size="12" text-anchor="{}" dominant-baseline="central">{}</text>"#,
+                    x,
+                    scale_y + 6.0,
+                    anchor,
+                    format_value(value)
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn finger_color(finger: Finger) -> Rgb {
+    FINGERS
+        .iter()
+        .find(|(f, _, _)| *f == finger)
+        .map(|(_, _, color)| *color)
+        .unwrap()
+}
+
+fn format_value(value: f64) -> String {
+    if !value.is_finite() {
+        String::new()
+    } else if value.abs() >= 100.0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.3}", value)
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
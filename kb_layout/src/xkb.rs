@@ -0,0 +1,151 @@
+use std::fmt::Write;
+
+use crate::board::{self, LEVEL_NAMES};
+use crate::{svg::escape, ExportError, Key, Keyboard, Layout, MatrixPosition};
+
+// ASCII characters whose keysyms aren't the character itself
+const KEYSYMS: [(char, &str); 33] = [
+    (' ', "space"),
+    ('!', "exclam"),
+    ('"', "quotedbl"),
+    ('#', "numbersign"),
+    ('$', "dollar"),
+    ('%', "percent"),
+    ('&', "ampersand"),
+    ('\'', "apostrophe"),
+    ('(', "parenleft"),
+    (')', "parenright"),
+    ('*', "asterisk"),
+    ('+', "plus"),
+    (',', "comma"),
+    ('-', "minus"),
+    ('.', "period"),
+    ('/', "slash"),
+    (':', "colon"),
+    (';', "semicolon"),
+    ('<', "less"),
+    ('=', "equal"),
+    ('>', "greater"),
+    ('?', "question"),
+    ('@', "at"),
+    ('[', "bracketleft"),
+    ('\\', "backslash"),
+    (']', "bracketright"),
+    ('^', "asciicircum"),
+    ('_', "underscore"),
+    ('`', "grave"),
+    ('{', "braceleft"),
+    ('|', "bar"),
+    ('}', "braceright"),
+    ('~', "asciitilde"),
+];
+
+/// Writes the layout as an XKB symbols file for a keyboard from `board::Board`, with a level for
+/// shift and, when the layout has an AltGr layer, two more for AltGr with Right Alt as AltGr.
+/// The levels come from `board::levels`
+pub fn symbols(
+    keyboard: &Keyboard,
+    layout: &Layout,
+    description: &str,
+) -> Result<String, ExportError> {
+    layout.check(keyboard)?;
+    let levels = board::levels(layout);
+    let level_count = if levels.iter().any(|levels| levels[2] != Key::None) {
+        4
+    } else {
+        2
+    };
+
+    let mut symbols = String::new();
+    writeln!(symbols, "default partial alphanumeric_keys").unwrap();
+    writeln!(symbols, "xkb_symbols \"basic\" {{").unwrap();
+    writeln!(
+        symbols,
+        "    name[Group1] = \"{}\";",
+        description.replace('"', "'")
+    )
+    .unwrap();
+    writeln!(symbols).unwrap();
+    for i in keyboard.matrix_rows().into_iter().flatten() {
+        let matrix_position = keyboard.buttons[i].matrix_position;
+        let name =
+            key_name(matrix_position).ok_or(ExportError::UnsupportedButton { matrix_position })?;
+        let keysyms = levels[i][..level_count]
+            .iter()
+            .zip(LEVEL_NAMES)
+            .map(|(key, level)| {
+                keysym(key).ok_or_else(|| ExportError::UnsupportedKey {
+                    layer: level.to_string(),
+                    key: key.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        writeln!(
+            symbols,
+            "    key <{}> {{ [ {} ] }};",
+            name,
+            keysyms.join(", ")
+        )
+        .unwrap();
+    }
+
+    if level_count == 4 {
+        writeln!(symbols).unwrap();
+        writeln!(symbols, "    include \"level3(ralt_switch)\"").unwrap();
+    }
+
+    writeln!(symbols, "}};").unwrap();
+    Ok(symbols)
+}
+
+/// The layout element of rules/evdev.xml that lists the symbols file installed as
+/// symbols/`name` in the keyboard settings
+pub fn rules_layout(name: &str, description: &str) -> String {
+    let mut layout = String::new();
+    writeln!(layout, "<layout>").unwrap();
+    writeln!(layout, "  <configItem>").unwrap();
+    writeln!(layout, "    <name>{}</name>", escape(name)).unwrap();
+    writeln!(layout, "    <shortDescription>en</shortDescription>").unwrap();
+    writeln!(
+        layout,
+        "    <description>{}</description>",
+        escape(description)
+    )
+    .unwrap();
+    writeln!(layout, "    <languageList>").unwrap();
+    writeln!(layout, "      <iso639Id>eng</iso639Id>").unwrap();
+    writeln!(layout, "    </languageList>").unwrap();
+    writeln!(layout, "  </configItem>").unwrap();
+    writeln!(layout, "</layout>").unwrap();
+    layout
+}
+
+// XKB's name for the key at a position of an ANSI or ISO board
+fn key_name(matrix_position: MatrixPosition) -> Option<String> {
+    let (row, col) = (matrix_position.row, matrix_position.col);
+    match (row, col) {
+        (0, 0) => Some("TLDE".to_string()),
+        (1, 13) | (2, 12) => Some("BKSL".to_string()),
+        (3, 0) => Some("LSGT".to_string()),
+        (0, 1..=12) | (1, 1..=12) | (2, 1..=11) | (3, 1..=10) => {
+            Some(format!("A{}{:02}", ['E', 'D', 'C', 'B'][row as usize], col))
+        }
+        _ => None,
+    }
+}
+
+fn keysym(key: &Key) -> Option<String> {
+    match key {
+        Key::None => Some("NoSymbol".to_string()),
+        Key::Char(c) if c.is_ascii_alphanumeric() => Some(c.to_string()),
+        Key::Char(c) if c.is_ascii() => KEYSYMS
+            .iter()
+            .find(|(keysym_char, _)| keysym_char == c)
+            .map(|(_, keysym)| keysym.to_string()),
+        Key::Char(c) => Some(format!("U{:04X}", *c as u32)),
+        Key::Dead(accent) => {
+            board::dead_key(*accent).map(|dead_key| format!("dead_{}", dead_key.name))
+        }
+        _ => None,
+    }
+}
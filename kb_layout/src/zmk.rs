@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::board::shifted;
+use crate::layout::{split_call, strip_comments};
+use crate::{ExportError, ImportError, Key, Keyboard, Layer, Layout, Modifier, NamedKey};
+
+// Characters and the names of their keys in dt-bindings/zmk/keys.h
+const CHARS: [(char, &str); 33] = [
+    (' ', "SPACE"),
+    (',', "COMMA"),
+    ('.', "DOT"),
+    ('/', "FSLH"),
+    (';', "SEMI"),
+    ('\'', "SQT"),
+    ('[', "LBKT"),
+    (']', "RBKT"),
+    ('\\', "BSLH"),
+    ('-', "MINUS"),
+    ('=', "EQUAL"),
+    ('`', "GRAVE"),
+    ('?', "QMARK"),
+    ('!', "EXCL"),
+    ('@', "AT"),
+    ('#', "HASH"),
+    ('$', "DOLLAR"),
+    ('%', "PRCNT"),
+    ('^', "CARET"),
+    ('&', "AMPS"),
+    ('*', "STAR"),
+    ('(', "LPAR"),
+    (')', "RPAR"),
+    ('_', "UNDER"),
+    ('+', "PLUS"),
+    ('{', "LBRC"),
+    ('}', "RBRC"),
+    ('|', "PIPE"),
+    (':', "COLON"),
+    ('"', "DQT"),
+    ('<', "LT"),
+    ('>', "GT"),
+    ('~', "TILDE"),
+];
+
+// Other names of keys in dt-bindings/zmk/keys.h and the ones the exporter writes
+const KEY_ALIASES: [(&str, &str); 53] = [
+    ("RETURN", "RET"),
+    ("ENTER", "RET"),
+    ("BACKSPACE", "BSPC"),
+    ("ESCAPE", "ESC"),
+    ("DELETE", "DEL"),
+    ("CAPSLOCK", "CAPS"),
+    ("PAGE_UP", "PG_UP"),
+    ("PAGE_DOWN", "PG_DN"),
+    ("LEFT_ARROW", "LEFT"),
+    ("RIGHT_ARROW", "RIGHT"),
+    ("UP_ARROW", "UP"),
+    ("DOWN_ARROW", "DOWN"),
+    ("SLASH", "FSLH"),
+    ("SEMICOLON", "SEMI"),
+    ("APOSTROPHE", "SQT"),
+    ("APOS", "SQT"),
+    ("SINGLE_QUOTE", "SQT"),
+    ("LEFT_BRACKET", "LBKT"),
+    ("RIGHT_BRACKET", "RBKT"),
+    ("BACKSLASH", "BSLH"),
+    ("PERIOD", "DOT"),
+    ("QUESTION", "QMARK"),
+    ("EXCLAMATION", "EXCL"),
+    ("DLLR", "DOLLAR"),
+    ("PERCENT", "PRCNT"),
+    ("ASTERISK", "STAR"),
+    ("UNDERSCORE", "UNDER"),
+    ("LEFT_PARENTHESIS", "LPAR"),
+    ("RIGHT_PARENTHESIS", "RPAR"),
+    ("LEFT_BRACE", "LBRC"),
+    ("RIGHT_BRACE", "RBRC"),
+    ("DOUBLE_QUOTES", "DQT"),
+    ("LESS_THAN", "LT"),
+    ("GREATER_THAN", "GT"),
+    ("LSHIFT", "LSHFT"),
+    ("LEFT_SHIFT", "LSHFT"),
+    ("LCTL", "LCTRL"),
+    ("LEFT_CONTROL", "LCTRL"),
+    ("LEFT_ALT", "LALT"),
+    ("LCMD", "LGUI"),
+    ("LWIN", "LGUI"),
+    ("LMETA", "LGUI"),
+    ("LEFT_GUI", "LGUI"),
+    ("RSHIFT", "RSHFT"),
+    ("RIGHT_SHIFT", "RSHFT"),
+    ("RCTL", "RCTRL"),
+    ("RIGHT_CONTROL", "RCTRL"),
+    ("RIGHT_ALT", "RALT"),
+    ("RCMD", "RGUI"),
+    ("RWIN", "RGUI"),
+    ("RMETA", "RGUI"),
+    ("RIGHT_GUI", "RGUI"),
+    ("SPC", "SPACE"),
+];
+
+/// Writes the layout as the .keymap devicetree of a ZMK keyboard, with a node per layer whose
+/// bindings list the keys in matrix order, one line per matrix row. ZMK can't type characters
+/// outside of ASCII, those are an error
+pub fn keymap(keyboard: &Keyboard, layout: &Layout) -> Result<String, ExportError> {
+    layout.check(keyboard)?;
+    let layer_names = layout.layer_identifiers();
+
+    let mut keymap = String::new();
+    writeln!(keymap, "#include <behaviors.dtsi>").unwrap();
+    writeln!(keymap, "#include <dt-bindings/zmk/keys.h>").unwrap();
+    writeln!(keymap).unwrap();
+    for (i, name) in layer_names.iter().enumerate() {
+        writeln!(keymap, "#define {} {}", name, i).unwrap();
+    }
+
+    writeln!(keymap).unwrap();
+    writeln!(keymap, "/ {{").unwrap();
+    writeln!(keymap, "    keymap {{").unwrap();
+    writeln!(keymap, "        compatible = \"zmk,keymap\";").unwrap();
+    let rows = keyboard.matrix_rows();
+    for (layer, name) in layout.layers.iter().zip(&layer_names) {
+        let binding_rows = layer.key_rows(&rows, |key| binding(key, &layer_names))?;
+        let width = binding_rows
+            .iter()
+            .flatten()
+            .map(String::len)
+            .max()
+            .unwrap_or(0);
+
+        writeln!(keymap).unwrap();
+        writeln!(keymap, "        {}_layer {{", name.to_lowercase()).unwrap();
+        writeln!(
+            keymap,
+            "            display-name = \"{}\";",
+            layer.name.replace('"', "\\\"")
+        )
+        .unwrap();
+        writeln!(keymap, "            bindings = <").unwrap();
+        for bindings in binding_rows {
+            let line = bindings
+                .iter()
+                .map(|binding| format!("{:<width$}", binding, width = width))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(keymap, "                {}", line.trim_end()).unwrap();
+        }
+
+        writeln!(keymap, "            >;").unwrap();
+        writeln!(keymap, "        }};").unwrap();
+    }
+
+    writeln!(keymap, "    }};").unwrap();
+    writeln!(keymap, "}};").unwrap();
+    Ok(keymap)
+}
+
+fn binding(key: &Key, layer_names: &[String]) -> Option<String> {
+    let layer_ref = |layer: usize| layer_names.get(layer);
+    match key {
+        Key::None => Some("&none".to_string()),
+        Key::Transparent => Some("&trans".to_string()),
+        Key::Char(_) | Key::Named(_) | Key::Modifier(_) => Some(format!("&kp {}", key_name(key)?)),
+        Key::Dead(_) => None,
+        Key::Momentary(layer) => Some(format!("&mo {}", layer_ref(*layer)?)),
+        Key::Toggle(layer) => Some(format!("&tog {}", layer_ref(*layer)?)),
+        Key::LayerTap { layer, tap } => {
+            Some(format!("&lt {} {}", layer_ref(*layer)?, key_name(tap)?))
+        }
+        Key::ModTap { modifier, tap } => Some(format!(
+            "&mt {} {}",
+            modifier_name(*modifier),
+            key_name(tap)?
+        )),
+    }
+}
+
+// The parameter of &kp, &lt and &mt
+fn key_name(key: &Key) -> Option<String> {
+    match key {
+        Key::Char(c @ 'a'..='z') => Some(c.to_ascii_uppercase().to_string()),
+        Key::Char(c @ 'A'..='Z') => Some(format!("LS({})", c)),
+        Key::Char(c @ '0'..='9') => Some(format!("N{}", c)),
+        Key::Char(c) => CHARS
+            .iter()
+            .find(|(named, _)| named == c)
+            .map(|(_, name)| name.to_string()),
+        Key::Named(named_key) => Some(
+            match named_key {
+                NamedKey::Space => "SPACE",
+                NamedKey::Enter => "RET",
+                NamedKey::Backspace => "BSPC",
+                NamedKey::Delete => "DEL",
+                NamedKey::Tab => "TAB",
+                NamedKey::Escape => "ESC",
+                NamedKey::CapsLock => "CAPS",
+                NamedKey::Left => "LEFT",
+                NamedKey::Right => "RIGHT",
+                NamedKey::Up => "UP",
+                NamedKey::Down => "DOWN",
+                NamedKey::Home => "HOME",
+                NamedKey::End => "END",
+                NamedKey::PageUp => "PG_UP",
+                NamedKey::PageDown => "PG_DN",
+            }
+            .to_string(),
+        ),
+        Key::Modifier(modifier) => Some(modifier_name(*modifier).to_string()),
+        _ => None,
+    }
+}
+
+fn modifier_name(modifier: Modifier) -> &'static str {
+    match modifier {
+        Modifier::LeftShift => "LSHFT",
+        Modifier::LeftControl => "LCTRL",
+        Modifier::LeftAlt => "LALT",
+        Modifier::LeftGui => "LGUI",
+        Modifier::RightShift => "RSHFT",
+        Modifier::RightControl => "RCTRL",
+        Modifier::RightAlt => "RALT",
+        Modifier::RightGui => "RGUI",
+    }
+}
+
+/// Reads the layers of the keymap node of a .keymap file, with the bindings of each layer in the
+/// matrix order of `keyboard`. Layers are referred to by number or by names #defined as numbers
+pub fn parse_keymap(keyboard: &Keyboard, source: &str) -> Result<Layout, ImportError> {
+    let source = strip_comments(source);
+    let syntax = |message: &str| ImportError::Syntax(message.to_string());
+    let defines = source
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().strip_prefix("#define")?.split_whitespace();
+            Some((parts.next()?, parts.next()?.parse::<usize>().ok()?))
+        })
+        .collect::<HashMap<_, _>>();
+    let layer_ref = |name: &str| name.parse().ok().or_else(|| defines.get(name).copied());
+
+    // The layers are the child nodes of the node with the keymap compatible
+    let compatible = "\"zmk,keymap\"";
+    let keymap = &source[source
+        .find(compatible)
+        .ok_or_else(|| syntax("there is no keymap node"))?
+        + compatible.len()..];
+    let mut nodes = Vec::new();
+    let mut depth = 0;
+    let mut name_start = 0;
+    let mut body_start = 0;
+    for (i, c) in keymap.char_indices() {
+        match c {
+            '{' => {
+                if depth == 0 {
+                    body_start = i + 1;
+                }
+                depth += 1;
+            }
+            '}' if depth == 0 => break,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    nodes.push((
+                        keymap[name_start..body_start - 1].trim(),
+                        &keymap[body_start..i],
+                    ));
+                    name_start = i + 1;
+                }
+            }
+            ';' if depth == 0 => name_start = i + 1,
+            _ => {}
+        }
+    }
+
+    let mut layers = Vec::new();
+    for (node_name, body) in nodes {
+        let name = property_string(body, "display-name")
+            .or_else(|| property_string(body, "label"))
+            .unwrap_or_else(|| node_name.trim_end_matches("_layer").to_string());
+        let bindings = body
+            .find("bindings")
+            .and_then(|start| {
+                let rest = &body[start..];
+                let open = rest.find('<')?;
+                let close = rest[open..].find('>')? + open;
+                Some(&rest[open + 1..close])
+            })
+            .ok_or_else(|| ImportError::Syntax(format!("layer {} has no bindings", name)))?;
+
+        // Every binding starts with the & of its behavior, followed by its parameters
+        let mut tokens = Vec::<Vec<&str>>::new();
+        for token in bindings.split_whitespace() {
+            match tokens.last_mut() {
+                Some(binding) if !token.starts_with('&') => binding.push(token),
+                _ => tokens.push(vec![token]),
+            }
+        }
+
+        let keys = tokens
+            .iter()
+            .map(|binding| {
+                parse_binding(binding, &layer_ref).ok_or_else(|| ImportError::UnknownKey {
+                    layer: name.clone(),
+                    key: binding.join(" "),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        layers.push(Layer::from_matrix_order(keyboard, name, keys)?);
+    }
+
+    if layers.is_empty() {
+        return Err(syntax("the keymap node has no layers"));
+    }
+
+    Ok(Layout { layers })
+}
+
+// The value of a string property like display-name = "Base";
+fn property_string(body: &str, property: &str) -> Option<String> {
+    let rest = body[body.find(property)? + property.len()..]
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start()
+        .strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].to_string())
+}
+
+fn parse_binding(binding: &[&str], layer_ref: &impl Fn(&str) -> Option<usize>) -> Option<Key> {
+    let tap = |name: &str| parse_key_name(name).map(Box::new);
+    match binding {
+        ["&none"] => Some(Key::None),
+        ["&trans"] => Some(Key::Transparent),
+        ["&kp", name] => parse_key_name(name),
+        ["&mo", layer] => Some(Key::Momentary(layer_ref(layer)?)),
+        ["&tog", layer] => Some(Key::Toggle(layer_ref(layer)?)),
+        ["&lt", layer, name] => Some(Key::LayerTap {
+            layer: layer_ref(layer)?,
+            tap: tap(name)?,
+        }),
+        ["&mt", modifier, name] => match parse_key_name(modifier)? {
+            Key::Modifier(modifier) => Some(Key::ModTap {
+                modifier,
+                tap: tap(name)?,
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_key_name(name: &str) -> Option<Key> {
+    let name = KEY_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name.to_string(), |(_, key_name)| key_name.to_string());
+    let name = match name.strip_prefix("NUMBER_") {
+        Some(digit) => format!("N{}", digit),
+        None => name,
+    };
+    match split_call(&name) {
+        Some(("LS" | "RS", arguments)) => match arguments[..] {
+            [name] => match parse_key_name(name)? {
+                Key::Char(c) => Some(Key::Char(shifted(c))),
+                _ => None,
+            },
+            _ => None,
+        },
+        Some(_) => None,
+        None => Key::basic_keys().find(|key| key_name(key).as_deref() == Some(name.as_str())),
+    }
+}
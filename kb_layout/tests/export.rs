@@ -0,0 +1,383 @@
+use std::path::Path;
+
+use kb_layout::{
+    board::Board,
+    kanata::{self, Remapper},
+    keylayout, klc, qmk, xkb, zmk, Button, Finger, Hand, Key, Keyboard, Layer, Layout,
+    MatrixPosition, Modifier, NamedKey, Position,
+};
+
+// Compares the output of an exporter with the golden file of the same name in tests/golden. Run
+// the tests with UPDATE_GOLDEN set to write the current output to the golden files instead
+fn assert_golden(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("could not read {}: {}", path.display(), err));
+    assert!(
+        actual == expected,
+        "the output differs from {}, run the tests with UPDATE_GOLDEN=1 and check the diff",
+        path.display()
+    );
+}
+
+// The US layout with accented letters and dead keys on AltGr, like US International. The AltGr
+// shift layer only sets the keys that don't type the shifted AltGr key
+fn international(board: Board) -> Layout {
+    let mut layout = board.us_layout();
+    let base = layout.layers[0].keys.clone();
+    let mut altgr = vec![Key::None; base.len()];
+    let mut altgr_shift = vec![Key::Transparent; base.len()];
+    let altgr_keys = [
+        ('\'', Key::Dead('´'), Key::Dead('¨')),
+        ('`', Key::Dead('`'), Key::Dead('~')),
+        ('6', Key::Dead('^'), Key::Char('¼')),
+        (',', Key::Dead('¸'), Key::Transparent),
+        ('e', Key::Char('é'), Key::Transparent),
+        ('n', Key::Char('ñ'), Key::Transparent),
+        ('5', Key::Char('€'), Key::None),
+    ];
+    for (c, key, shifted_key) in altgr_keys {
+        let i = base.iter().position(|key| *key == Key::Char(c)).unwrap();
+        altgr[i] = key;
+        altgr_shift[i] = shifted_key;
+    }
+
+    layout.layers.push(Layer {
+        name: "altgr".to_string(),
+        keys: altgr,
+    });
+    layout.layers.push(Layer {
+        name: "altgr shift".to_string(),
+        keys: altgr_shift,
+    });
+    layout
+}
+
+// The US layout with home row mods, a layer-tap on the apostrophe key and a symbol layer with
+// the shifted digits on the home row
+fn home_row_mods(board: Board) -> Layout {
+    let mut layout = board.us_layout();
+    let base = layout.layers[0].keys.clone();
+    let position = |c| base.iter().position(|key| *key == Key::Char(c)).unwrap();
+    let mods = [
+        ('a', Modifier::LeftGui),
+        ('s', Modifier::LeftAlt),
+        ('d', Modifier::LeftControl),
+        ('f', Modifier::LeftShift),
+        ('j', Modifier::RightShift),
+        ('k', Modifier::RightControl),
+        ('l', Modifier::RightAlt),
+        (';', Modifier::RightGui),
+    ];
+    for (c, modifier) in mods {
+        layout.layers[0].keys[position(c)] = Key::ModTap {
+            modifier,
+            tap: Box::new(Key::Char(c)),
+        };
+    }
+
+    layout.layers[0].keys[position('\'')] = Key::LayerTap {
+        layer: 1,
+        tap: Box::new(Key::Char('\'')),
+    };
+
+    let mut sym = vec![Key::Transparent; base.len()];
+    for (c, symbol) in "asdfghjkl;".chars().zip("!@#$%^&*()".chars()) {
+        sym[position(c)] = Key::Char(symbol);
+    }
+
+    sym[position('z')] = Key::None;
+    sym[position('/')] = Key::Toggle(0);
+    layout.layers.push(Layer {
+        name: "sym".to_string(),
+        keys: sym,
+    });
+    layout
+}
+
+// A split keyboard with three rows of ten keys and three thumb keys on each side, the thumb keys
+// are on matrix row 3
+fn split_keyboard() -> Keyboard {
+    let mut buttons = Vec::new();
+    for row in 0..4u8 {
+        let cols = if row < 3 { 0..10 } else { 2..8 };
+        for col in cols {
+            let finger = match (row, col) {
+                (3, _) => Finger::Thumb,
+                (_, 0 | 9) => Finger::Pinky,
+                (_, 1 | 8) => Finger::Ring,
+                (_, 2 | 7) => Finger::Middle,
+                _ => Finger::Pointer,
+            };
+            let position = Position {
+                x: col as f64,
+                y: row as f64,
+            };
+            let home_offset = Position {
+                x: 0.0,
+                y: position.y - 1.0,
+            };
+            buttons.push(Button {
+                hand: if col < 5 { Hand::Left } else { Hand::Right },
+                finger,
+                position,
+                matrix_position: MatrixPosition { col, row },
+                home_offset,
+                cost: home_offset.y.abs(),
+            });
+        }
+    }
+
+    Keyboard { buttons }
+}
+
+// Colemak with home row mods, a navigation layer and a symbol layer held with the thumbs
+fn split_layout(keyboard: &Keyboard) -> Layout {
+    let chars = |row: &str| row.chars().map(Key::Char).collect::<Vec<_>>();
+    let mod_tap = |modifier, c| Key::ModTap {
+        modifier,
+        tap: Box::new(Key::Char(c)),
+    };
+    let layer_tap = |layer, key| Key::LayerTap {
+        layer,
+        tap: Box::new(Key::Named(key)),
+    };
+
+    let mut base = chars("qwfpgjluy;");
+    base.extend([
+        mod_tap(Modifier::LeftGui, 'a'),
+        mod_tap(Modifier::LeftAlt, 'r'),
+        mod_tap(Modifier::LeftControl, 's'),
+        mod_tap(Modifier::LeftShift, 't'),
+        Key::Char('d'),
+        Key::Char('h'),
+        mod_tap(Modifier::RightShift, 'n'),
+        mod_tap(Modifier::RightControl, 'e'),
+        mod_tap(Modifier::RightAlt, 'i'),
+        mod_tap(Modifier::RightGui, 'o'),
+    ]);
+    base.extend(chars("zxcvbkm,./"));
+    base.extend([
+        Key::Named(NamedKey::Escape),
+        layer_tap(1, NamedKey::Space),
+        Key::Named(NamedKey::Tab),
+        Key::Named(NamedKey::Enter),
+        layer_tap(2, NamedKey::Backspace),
+        Key::Named(NamedKey::Delete),
+    ]);
+
+    let mut nav = chars("1234567890");
+    nav.extend([
+        Key::Modifier(Modifier::LeftGui),
+        Key::Modifier(Modifier::LeftAlt),
+        Key::Modifier(Modifier::LeftControl),
+        Key::Modifier(Modifier::LeftShift),
+        Key::None,
+        Key::Named(NamedKey::Left),
+        Key::Named(NamedKey::Down),
+        Key::Named(NamedKey::Up),
+        Key::Named(NamedKey::Right),
+        Key::Named(NamedKey::CapsLock),
+    ]);
+    nav.extend(vec![Key::None; 5]);
+    nav.extend([
+        Key::Named(NamedKey::Home),
+        Key::Named(NamedKey::PageDown),
+        Key::Named(NamedKey::PageUp),
+        Key::Named(NamedKey::End),
+        Key::None,
+    ]);
+    nav.extend([Key::Transparent, Key::Transparent, Key::Transparent]);
+    nav.extend([Key::Toggle(2), Key::Transparent, Key::Transparent]);
+
+    let mut sym = chars("!@#$%^&*()");
+    sym.extend(chars("`~-_=+[]{}"));
+    sym.extend(chars("\\|'\"<>:?"));
+    sym.extend([Key::None, Key::None]);
+    sym.extend([Key::Transparent, Key::Momentary(1), Key::Transparent]);
+    sym.extend([Key::Transparent, Key::Transparent, Key::Transparent]);
+
+    let layer = |name: &str, keys| Layer::from_matrix_order(keyboard, name.to_string(), keys);
+    Layout {
+        layers: vec![
+            layer("base", base).unwrap(),
+            layer("nav", nav).unwrap(),
+            layer("sym", sym).unwrap(),
+        ],
+    }
+}
+
+// The keys of the layers of an imported layout match those of the exported one
+fn assert_same_keys(imported: &Layout, exported: &Layout) {
+    assert_eq!(imported.layers.len(), exported.layers.len());
+    for (imported, exported) in imported.layers.iter().zip(&exported.layers) {
+        assert_eq!(imported.keys, exported.keys, "layer {}", exported.name);
+    }
+}
+
+#[test]
+fn qmk_keymap_c() {
+    let keyboard = split_keyboard();
+    let layout = split_layout(&keyboard);
+    let keymap = qmk::keymap_c(&keyboard, &layout, "LAYOUT_split_3x5_3").unwrap();
+    assert_golden("split.c", &keymap);
+    assert_same_keys(&qmk::parse_keymap_c(&keyboard, &keymap).unwrap(), &layout);
+}
+
+#[test]
+fn qmk_unicode() {
+    let keyboard = split_keyboard();
+    let mut layout = split_layout(&keyboard);
+    layout.layers[2].keys[0] = Key::Char('é');
+    let keymap = qmk::keymap_c(&keyboard, &layout, "LAYOUT").unwrap();
+    assert!(keymap.contains("UC(0x00E9)"), "{}", keymap);
+}
+
+#[test]
+fn zmk_keymap() {
+    let keyboard = split_keyboard();
+    let layout = split_layout(&keyboard);
+    let keymap = zmk::keymap(&keyboard, &layout).unwrap();
+    assert_golden("split.keymap", &keymap);
+    assert_same_keys(&zmk::parse_keymap(&keyboard, &keymap).unwrap(), &layout);
+}
+
+#[test]
+fn zmk_rejects_non_ascii() {
+    let keyboard = split_keyboard();
+    let mut layout = split_layout(&keyboard);
+    layout.layers[2].keys[0] = Key::Char('é');
+    assert!(zmk::keymap(&keyboard, &layout).is_err());
+}
+
+#[test]
+fn klc_us() {
+    let source = klc::source(
+        &Board::Ansi.keyboard(),
+        &Board::Ansi.us_layout(),
+        "us",
+        "English (US)",
+    )
+    .unwrap();
+    assert_golden("us.klc", &source);
+}
+
+#[test]
+fn klc_dead_keys() {
+    let source = klc::source(
+        &Board::Iso.keyboard(),
+        &international(Board::Iso),
+        "us intl",
+        "English (US, international)",
+    )
+    .unwrap();
+    assert_golden("international.klc", &source);
+}
+
+#[test]
+fn klc_encoding() {
+    let bytes = klc::encode("KBD\tü");
+    assert_eq!(
+        bytes,
+        [0xff, 0xfe, b'K', 0, b'B', 0, b'D', 0, b'\t', 0, 0xfc, 0]
+    );
+}
+
+#[test]
+fn keylayout_us() {
+    let keylayout = keylayout::keylayout(
+        &Board::Ansi.keyboard(),
+        &Board::Ansi.us_layout(),
+        "English (US)",
+    )
+    .unwrap();
+    assert_golden("us.keylayout", &keylayout);
+}
+
+#[test]
+fn keylayout_dead_keys() {
+    let keylayout = keylayout::keylayout(
+        &Board::Iso.keyboard(),
+        &international(Board::Iso),
+        "English (US, international)",
+    )
+    .unwrap();
+    assert_golden("international.keylayout", &keylayout);
+}
+
+#[test]
+fn xkb_us() {
+    let symbols = xkb::symbols(
+        &Board::Ansi.keyboard(),
+        &Board::Ansi.us_layout(),
+        "English (US)",
+    )
+    .unwrap();
+    assert_golden("us.xkb", &symbols);
+}
+
+#[test]
+fn xkb_altgr() {
+    let symbols = xkb::symbols(
+        &Board::Iso.keyboard(),
+        &international(Board::Iso),
+        "English (US, international)",
+    )
+    .unwrap();
+    assert_golden("international.xkb", &symbols);
+}
+
+#[test]
+fn kanata_home_row_mods() {
+    let config = kanata::config(
+        &Board::Ansi.keyboard(),
+        &home_row_mods(Board::Ansi),
+        Remapper::Kanata,
+    )
+    .unwrap();
+    assert_golden("home_row_mods.kbd", &config);
+}
+
+#[test]
+fn kmonad_home_row_mods() {
+    let config = kanata::config(
+        &Board::Iso.keyboard(),
+        &home_row_mods(Board::Iso),
+        Remapper::Kmonad,
+    )
+    .unwrap();
+    assert_golden("home_row_mods_kmonad.kbd", &config);
+}
+
+#[test]
+fn kanata_alias_names_are_unique() {
+    let mut layout = Board::Ansi.us_layout();
+    // Both taps become lsft__ in a name, the [ key has the same mod-tap as the - key
+    for (c, tap) in [('-', '-'), ('=', '='), ('[', '-')] {
+        let i = layout.layers[0]
+            .keys
+            .iter()
+            .position(|key| *key == Key::Char(c))
+            .unwrap();
+        layout.layers[0].keys[i] = Key::ModTap {
+            modifier: Modifier::LeftShift,
+            tap: Box::new(Key::Char(tap)),
+        };
+    }
+
+    let config = kanata::config(&Board::Ansi.keyboard(), &layout, Remapper::Kanata).unwrap();
+    assert!(
+        config.contains("  lsft__ (tap-hold 200 200 - lsft)\n")
+            && config.contains("  lsft___2 (tap-hold 200 200 = lsft)\n"),
+        "{}",
+        config
+    );
+    assert_eq!(config.matches("@lsft__ ").count(), 2, "{}", config);
+}
@@ -0,0 +1,28 @@
+#include QMK_KEYBOARD_H
+
+enum layers {
+    BASE,
+    NAV,
+    SYM,
+};
+
+const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {
+    [BASE] = LAYOUT_split_3x5_3(
+        KC_Q,               KC_W,               KC_F,               KC_P,               KC_G,               KC_J,               KC_L,               KC_U,               KC_Y,               KC_SCLN,
+        MT(MOD_LGUI, KC_A), MT(MOD_LALT, KC_R), MT(MOD_LCTL, KC_S), MT(MOD_LSFT, KC_T), KC_D,               KC_H,               MT(MOD_RSFT, KC_N), MT(MOD_RCTL, KC_E), MT(MOD_RALT, KC_I), MT(MOD_RGUI, KC_O),
+        KC_Z,               KC_X,               KC_C,               KC_V,               KC_B,               KC_K,               KC_M,               KC_COMM,            KC_DOT,             KC_SLSH,
+        KC_ESC,             LT(NAV, KC_SPC),    KC_TAB,             KC_ENT,             LT(SYM, KC_BSPC),   KC_DEL
+    ),
+    [NAV] = LAYOUT_split_3x5_3(
+        KC_1,    KC_2,    KC_3,    KC_4,    KC_5,    KC_6,    KC_7,    KC_8,    KC_9,    KC_0,
+        KC_LGUI, KC_LALT, KC_LCTL, KC_LSFT, KC_NO,   KC_LEFT, KC_DOWN, KC_UP,   KC_RGHT, KC_CAPS,
+        KC_NO,   KC_NO,   KC_NO,   KC_NO,   KC_NO,   KC_HOME, KC_PGDN, KC_PGUP, KC_END,  KC_NO,
+        KC_TRNS, KC_TRNS, KC_TRNS, TG(SYM), KC_TRNS, KC_TRNS
+    ),
+    [SYM] = LAYOUT_split_3x5_3(
+        KC_EXLM, KC_AT,   KC_HASH, KC_DLR,  KC_PERC, KC_CIRC, KC_AMPR, KC_ASTR, KC_LPRN, KC_RPRN,
+        KC_GRV,  KC_TILD, KC_MINS, KC_UNDS, KC_EQL,  KC_PLUS, KC_LBRC, KC_RBRC, KC_LCBR, KC_RCBR,
+        KC_BSLS, KC_PIPE, KC_QUOT, KC_DQUO, KC_LABK, KC_RABK, KC_COLN, KC_QUES, KC_NO,   KC_NO,
+        KC_TRNS, MO(NAV), KC_TRNS, KC_TRNS, KC_TRNS, KC_TRNS
+    ),
+};
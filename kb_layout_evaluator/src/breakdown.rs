@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::ngrams::{self, Ngrams};
+use crate::{
+    calc_distance, is_same_finger, is_same_hand_no_roll, measure, Keyboard, Metrics,
+    BREAKDOWN_OFFENDERS, FINGER_START_POSITIONS, SAME_FINGER_PENALTY, SAME_HAND_PENALTY,
+};
+
+/// Why a layout got the fitness it got: every component of the fitness along with the n-grams
+/// that cost the most for each metric
+#[derive(Serialize)]
+pub struct Breakdown {
+    pub fitness: f64,
+    /// Costs that are added together, the fitness is their sum times the multipliers
+    pub terms: Vec<Term>,
+    pub multipliers: Vec<Multiplier>,
+    pub metrics: Metrics,
+    pub offenders: Vec<Offenders>,
+    /// What typing each key adds to the terms, before the multipliers
+    pub key_costs: HashMap<char, f64>,
+}
+
+#[derive(Serialize)]
+pub struct Term {
+    pub name: &'static str,
+    pub value: f64,
+    pub weight: f64,
+}
+
+#[derive(Serialize)]
+pub struct Multiplier {
+    pub name: &'static str,
+    pub value: f64,
+}
+
+/// The n-grams that add the most to a metric, most costly first
+#[derive(Serialize)]
+pub struct Offenders {
+    pub metric: &'static str,
+    pub bigrams: Vec<NGram>,
+    pub trigrams: Vec<NGram>,
+}
+
+#[derive(Serialize)]
+pub struct NGram {
+    pub keys: String,
+    pub count: usize,
+    /// What the n-gram adds to the metric's term, before the multipliers
+    pub cost: f64,
+}
+
+// Occurrences and total cost of each n-gram
+#[derive(Default)]
+struct Tally(HashMap<String, (usize, f64)>);
+
+impl Tally {
+    // `count` occurrences that cost `cost` each
+    fn add(&mut self, keys: &[char], count: usize, cost: f64) {
+        let entry = self.0.entry(keys.iter().collect()).or_default();
+        entry.0 += count;
+        entry.1 += count as f64 * cost;
+    }
+
+    fn top(self) -> Vec<NGram> {
+        let mut ngrams = self
+            .0
+            .into_iter()
+            .map(|(keys, (count, cost))| NGram { keys, count, cost })
+            .collect::<Vec<_>>();
+        ngrams.sort_by(|left, right| {
+            right
+                .cost
+                .partial_cmp(&left.cost)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| left.keys.cmp(&right.keys))
+        });
+        ngrams.truncate(BREAKDOWN_OFFENDERS);
+        ngrams
+    }
+}
+
+#[derive(Default)]
+struct Tallies {
+    distance_bigrams: Tally,
+    same_finger_bigrams: Tally,
+    same_finger_trigrams: Tally,
+    same_hand_bigrams: Tally,
+    same_hand_trigrams: Tally,
+}
+
+impl Tallies {
+    // Charges the same finger and same hand n-grams ending at a key press to their tallies and
+    // to the key
+    fn add_press(
+        &mut self,
+        [prev_prev_key, prev_key, key]: [char; 3],
+        [same_finger, same_hand]: [(bool, bool); 2],
+        count: usize,
+        key_cost: &mut f64,
+    ) {
+        let (prev_same_finger, same_finger) = same_finger;
+        let (prev_same_hand, same_hand) = same_hand;
+        if same_finger {
+            self.same_finger_bigrams
+                .add(&[prev_key, key], count, SAME_FINGER_PENALTY);
+            *key_cost += count as f64 * SAME_FINGER_PENALTY;
+            if prev_same_finger {
+                self.same_finger_trigrams.add(
+                    &[prev_prev_key, prev_key, key],
+                    count,
+                    SAME_FINGER_PENALTY,
+                );
+            }
+        }
+
+        if same_hand {
+            self.same_hand_bigrams
+                .add(&[prev_key, key], count, SAME_HAND_PENALTY);
+            *key_cost += count as f64 * SAME_HAND_PENALTY;
+            if prev_same_hand {
+                self.same_hand_trigrams.add(
+                    &[prev_prev_key, prev_key, key],
+                    count,
+                    SAME_HAND_PENALTY,
+                );
+            }
+        }
+    }
+
+    fn breakdown(self, metrics: Metrics, key_costs: HashMap<char, f64>) -> Breakdown {
+        Breakdown {
+            fitness: metrics.fitness(),
+            terms: metrics
+                .terms()
+                .into_iter()
+                .map(|(name, value, weight)| Term {
+                    name,
+                    value,
+                    weight,
+                })
+                .collect(),
+            multipliers: metrics
+                .multipliers()
+                .into_iter()
+                .map(|(name, value)| Multiplier { name, value })
+                .collect(),
+            offenders: vec![
+                Offenders {
+                    metric: "distance",
+                    bigrams: self.distance_bigrams.top(),
+                    trigrams: Vec::new(),
+                },
+                Offenders {
+                    metric: "same finger",
+                    bigrams: self.same_finger_bigrams.top(),
+                    trigrams: self.same_finger_trigrams.top(),
+                },
+                Offenders {
+                    metric: "same hand",
+                    bigrams: self.same_hand_bigrams.top(),
+                    trigrams: self.same_hand_trigrams.top(),
+                },
+            ],
+            metrics,
+            key_costs,
+        }
+    }
+}
+
+pub fn breakdown(keyboard: &Keyboard, input: &str, letter_freq: &HashMap<char, f64>) -> Breakdown {
+    let metrics = measure(keyboard, input, letter_freq);
+
+    // Distance is attributed to the key a finger moves from and the key it moves to, which are
+    // not necessarily typed one after the other, '-' is where the finger starts. Bigrams are
+    // counted like in `measure`, trigrams are the ones where both of their bigrams count. Each key
+    // is charged with the costs of the bigrams it ends
+    let mut finger_positions = FINGER_START_POSITIONS;
+    let mut finger_keys = ['-'; 10];
+    let mut tallies = Tallies::default();
+    let mut prev_finger_idx = 0;
+    let mut prev_key = '-';
+    let mut prev_prev_key = '-';
+    let mut prev_same_finger = false;
+    let mut prev_same_hand = false;
+    let mut key_costs = HashMap::new();
+    for key in input.chars() {
+        let finger_idx = keyboard.key_finger(key);
+        let (x, y) = keyboard.key_pos(key);
+        let (x, y) = (x as isize, y as isize);
+        let (start_x, start_y) = finger_positions[finger_idx];
+        let distance = calc_distance(start_x, start_y, x, y);
+        tallies
+            .distance_bigrams
+            .add(&[finger_keys[finger_idx], key], 1, distance);
+        let key_cost = key_costs.entry(key).or_insert(0.0);
+        *key_cost += distance;
+
+        let same_finger = is_same_finger(prev_finger_idx, prev_key, finger_idx, key);
+        let same_hand = is_same_hand_no_roll(prev_finger_idx, finger_idx);
+        tallies.add_press(
+            [prev_prev_key, prev_key, key],
+            [(prev_same_finger, same_finger), (prev_same_hand, same_hand)],
+            1,
+            key_cost,
+        );
+
+        finger_positions[finger_idx] = (x, y);
+        finger_keys[finger_idx] = key;
+        prev_finger_idx = finger_idx;
+        prev_prev_key = prev_key;
+        prev_key = key;
+        prev_same_finger = same_finger;
+        prev_same_hand = same_hand;
+    }
+
+    tallies.breakdown(metrics, key_costs)
+}
+
+/// The breakdown of the metrics of `Ngrams::measure`. Distance is attributed to the key typed
+/// before a press when the same finger typed it, and to '-' when the finger moves from its keys
+pub fn ngram_breakdown(keyboard: &Keyboard, ngrams: &Ngrams) -> Breakdown {
+    let metrics = ngrams.measure(keyboard);
+    let finger_distances = ngrams.finger_distances(keyboard);
+    let mut tallies = Tallies::default();
+    let mut key_costs = HashMap::new();
+    for ([prev_prev_key, prev_key, key], count) in ngrams.presses() {
+        let finger_idx = keyboard.key_finger(key);
+        let prev_finger_idx = ngrams::finger(keyboard, prev_key);
+        let distance = ngrams::press_distance(keyboard, &finger_distances, prev_key, key);
+        let from = if prev_key != '-' && prev_finger_idx == finger_idx {
+            prev_key
+        } else {
+            '-'
+        };
+        tallies.distance_bigrams.add(&[from, key], count, distance);
+        let key_cost = key_costs.entry(key).or_insert(0.0);
+        *key_cost += count as f64 * distance;
+
+        let prev_prev_finger_idx = ngrams::finger(keyboard, prev_prev_key);
+        let prev_same_finger = prev_key != '-'
+            && is_same_finger(
+                prev_prev_finger_idx,
+                prev_prev_key,
+                prev_finger_idx,
+                prev_key,
+            );
+        let prev_same_hand =
+            prev_key != '-' && is_same_hand_no_roll(prev_prev_finger_idx, prev_finger_idx);
+        tallies.add_press(
+            [prev_prev_key, prev_key, key],
+            [
+                (
+                    prev_same_finger,
+                    is_same_finger(prev_finger_idx, prev_key, finger_idx, key),
+                ),
+                (
+                    prev_same_hand,
+                    is_same_hand_no_roll(prev_finger_idx, finger_idx),
+                ),
+            ],
+            count,
+            key_cost,
+        );
+    }
+
+    tallies.breakdown(metrics, key_costs)
+}
+
+impl Breakdown {
+    pub fn print(&self) {
+        println!("fitness: {:.1}", self.fitness);
+        for term in &self.terms {
+            println!(
+                "  {}: {:.1} x {} = {:.1}",
+                term.name,
+                term.value,
+                term.weight,
+                term.value * term.weight
+            );
+        }
+
+        for multiplier in &self.multipliers {
+            println!("  x {}: {:.4}", multiplier.name, multiplier.value);
+        }
+
+        for offenders in &self.offenders {
+            let format_ngrams = |ngrams: &[NGram]| {
+                ngrams
+                    .iter()
+                    .map(|ngram| format!("{} ({}, {:.1})", ngram.keys, ngram.count, ngram.cost))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            println!(
+                "{} bigrams: {}",
+                offenders.metric,
+                format_ngrams(&offenders.bigrams)
+            );
+            if !offenders.trigrams.is_empty() {
+                println!(
+                    "{} trigrams: {}",
+                    offenders.metric,
+                    format_ngrams(&offenders.trigrams)
+                );
+            }
+        }
+    }
+}
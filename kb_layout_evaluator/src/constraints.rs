@@ -0,0 +1,194 @@
+use kb_layout::Hand;
+use rand::{rngs::StdRng, seq::SliceRandom};
+
+use crate::{column_finger, Keyboard};
+
+/// A set of positions on the 3x10 grid, stored as one bit per position (column major, like
+/// `Keyboard::keys`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region(u32);
+
+impl Region {
+    pub const ALL: Region = Region((1 << 30) - 1);
+
+    pub const fn position(x: usize, y: usize) -> Self {
+        Self(1 << (x * 3 + y))
+    }
+
+    pub const fn column(x: usize) -> Self {
+        Self(0b111 << (x * 3))
+    }
+
+    pub const fn row(y: usize) -> Self {
+        let mut bits = 0;
+        let mut x = 0;
+        while x < 10 {
+            bits |= Self::position(x, y).0;
+            x += 1;
+        }
+
+        Self(bits)
+    }
+
+    pub const fn hand(hand: Hand) -> Self {
+        match hand {
+            Hand::Left => Self(Self::ALL.0 >> 15),
+            Hand::Right => Self(Self::ALL.0 & !(Self::ALL.0 >> 15)),
+        }
+    }
+
+    pub const fn finger(finger: usize) -> Self {
+        let mut bits = 0;
+        let mut x = 0;
+        while x < 10 {
+            if column_finger(x) == finger {
+                bits |= Self::column(x).0;
+            }
+
+            x += 1;
+        }
+
+        Self(bits)
+    }
+
+    pub const fn and(self, other: Region) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub const fn or(self, other: Region) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn without(self, other: Region) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.0 & Self::position(x, y).0 != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn positions(self) -> impl Iterator<Item = (usize, usize)> {
+        (0..30)
+            .map(|i| (i / 3, i % 3))
+            .filter(move |&(x, y)| self.contains(x, y))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Constraint {
+    /// The keys may only be placed within the region, a region of a single position pins the key
+    Hard {
+        keys: &'static [char],
+        region: Region,
+    },
+    /// The keys should be placed within the region, fitness is penalized by `penalty` times the
+    /// share of the keys that are placed outside of it
+    Soft {
+        keys: &'static [char],
+        region: Region,
+        penalty: f64,
+    },
+}
+
+impl Constraint {
+    pub const fn pin(keys: &'static [char], x: usize, y: usize) -> Self {
+        Self::Hard {
+            keys,
+            region: Region::position(x, y),
+        }
+    }
+}
+
+/// Positions a key may be placed at according to the hard constraints
+pub fn allowed_region(key: char, constraints: &[Constraint]) -> Region {
+    constraints
+        .iter()
+        .fold(Region::ALL, |allowed, constraint| match constraint {
+            Constraint::Hard { keys, region } if keys.contains(&key) => allowed.and(*region),
+            _ => allowed,
+        })
+}
+
+pub fn can_place(key: char, x: usize, y: usize, constraints: &[Constraint]) -> bool {
+    allowed_region(key, constraints).contains(x, y)
+}
+
+/// Whether swapping the keys at the two positions keeps the hard constraints satisfied
+pub fn can_swap(
+    keyboard: &Keyboard,
+    (x1, y1): (usize, usize),
+    (x2, y2): (usize, usize),
+    constraints: &[Constraint],
+) -> bool {
+    can_place(keyboard.keys[x1][y1], x2, y2, constraints)
+        && can_place(keyboard.keys[x2][y2], x1, y1, constraints)
+}
+
+pub fn is_satisfied(keyboard: &Keyboard, constraints: &[Constraint]) -> bool {
+    (0..30).all(|i| {
+        let (x, y) = (i / 3, i % 3);
+        can_place(keyboard.keys[x][y], x, y, constraints)
+    })
+}
+
+// Keys that aren't on the keyboard yet don't count as violations
+pub fn soft_penalty(keyboard: &Keyboard, constraints: &[Constraint]) -> f64 {
+    constraints
+        .iter()
+        .map(|constraint| match constraint {
+            Constraint::Soft {
+                keys,
+                region,
+                penalty,
+            } => {
+                let violations = keys
+                    .iter()
+                    .filter(|&&key| {
+                        if !keyboard.contains(key) {
+                            return false;
+                        }
+
+                        let (x, y) = keyboard.key_pos(key);
+                        !region.contains(x, y)
+                    })
+                    .count();
+                penalty * violations as f64 / keys.len() as f64
+            }
+            Constraint::Hard { .. } => 0.0,
+        })
+        .sum()
+}
+
+/// Places the missing keys on the empty ('-') positions of the keyboard at random, without
+/// violating any hard constraint. Returns false if the keys could not be placed
+pub fn fill(
+    keyboard: &mut Keyboard,
+    mut missing: Vec<char>,
+    constraints: &[Constraint],
+    rng: &mut StdRng,
+) -> bool {
+    missing.shuffle(rng);
+    // Place the most constrained keys first so that they aren't blocked by keys that could go
+    // anywhere
+    missing.sort_by_key(|&key| allowed_region(key, constraints).len());
+    for key in missing {
+        let empty_positions = allowed_region(key, constraints)
+            .positions()
+            .filter(|&(x, y)| keyboard.keys[x][y] == '-')
+            .collect::<Vec<_>>();
+        match empty_positions.choose(rng) {
+            Some(&(x, y)) => keyboard.keys[x][y] = key,
+            None => return false,
+        }
+    }
+
+    true
+}
@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+
+use crate::{
+    calc_distance, column_finger, constraints, finger_usage_penalty, hand_balance_multiplier,
+    is_same_finger, is_same_hand_no_roll, mouse_shortcut_multiplier, unreachable_shortcut_count,
+    Keyboard, Metrics, CHARS, CONSTRAINTS, FINGER_START_POSITIONS, FINGER_TARGET_USAGE,
+    SAME_FINGER_PENALTY, SAME_HAND_PENALTY, TARGET_LEFT_HAND_USAGE,
+};
+
+fn char_index(key: char) -> usize {
+    CHARS.iter().position(|&c| c == key).unwrap()
+}
+
+// Position of each key, indexed by position in CHARS
+fn key_positions(keyboard: &Keyboard) -> [(usize, usize); 30] {
+    let mut positions = [(0, 0); 30];
+    for x in 0..10 {
+        for y in 0..3 {
+            positions[char_index(keyboard.keys[x][y])] = (x, y);
+        }
+    }
+
+    positions
+}
+
+/// Evaluates layouts like `measure`, but keeps the input broken down by key so that the effect of
+/// swapping two keys can be computed by only looking at the bigrams involving the swapped keys
+/// and the movements of the fingers to and from the presses of the swapped keys
+pub struct DeltaEvaluator<'a> {
+    letter_freq: &'a HashMap<char, f64>,
+    first_key: Option<usize>,
+    // Number of times each key is followed by each other key, indexed by position in CHARS
+    bigrams: Vec<[usize; 30]>,
+    // Indices into the input where each key is typed, indexed by position in CHARS
+    occurrences: Vec<Vec<usize>>,
+}
+
+/// The metrics of a layout along with the state needed to update them when swapping keys
+#[derive(Clone)]
+pub struct Evaluation {
+    pub metrics: Metrics,
+    positions: [(usize, usize); 30],
+    finger_distances: [f64; 10],
+}
+
+impl<'a> DeltaEvaluator<'a> {
+    pub fn new(input: &str, letter_freq: &'a HashMap<char, f64>) -> Self {
+        let input = input.chars().map(char_index).collect::<Vec<_>>();
+        let mut bigrams = vec![[0; 30]; 30];
+        for bigram in input.windows(2) {
+            bigrams[bigram[0]][bigram[1]] += 1;
+        }
+
+        let mut occurrences = vec![Vec::new(); 30];
+        for (i, &key) in input.iter().enumerate() {
+            occurrences[key].push(i);
+        }
+
+        Self {
+            letter_freq,
+            first_key: input.first().copied(),
+            bigrams,
+            occurrences,
+        }
+    }
+
+    pub fn evaluate(&self, keyboard: &Keyboard) -> Evaluation {
+        let positions = key_positions(keyboard);
+        let mut finger_distances = [0.0; 10];
+        for (finger_idx, distance) in finger_distances.iter_mut().enumerate() {
+            *distance = self.finger_distance(&positions, finger_idx);
+        }
+
+        let (same_finger_count, same_hand_no_roll_count) = self.bigram_counts(
+            &positions,
+            (0..30)
+                .flat_map(|i| (0..30).map(move |j| (Some(i), j)))
+                .chain(self.first_bigram()),
+        );
+        Evaluation {
+            metrics: self.metrics(
+                keyboard,
+                &positions,
+                &finger_distances,
+                same_finger_count,
+                same_hand_no_roll_count,
+            ),
+            positions,
+            finger_distances,
+        }
+    }
+
+    /// The evaluation of the keyboard after swapping the keys at the two positions, given the
+    /// evaluation of the keyboard before the swap
+    pub fn swap(
+        &self,
+        keyboard: &Keyboard,
+        evaluation: &Evaluation,
+        first: (usize, usize),
+        second: (usize, usize),
+    ) -> Evaluation {
+        let first_key = char_index(keyboard.keys[first.0][first.1]);
+        let second_key = char_index(keyboard.keys[second.0][second.1]);
+        let positions = evaluation.positions;
+        let mut swapped_positions = positions;
+        swapped_positions.swap(first_key, second_key);
+        let mut swapped_keyboard = keyboard.clone();
+        swapped_keyboard.swap(first, second);
+
+        let affected_bigrams = || {
+            (0..30)
+                .flat_map(move |i| [(Some(i), first_key), (Some(i), second_key)])
+                .chain(
+                    (0..30)
+                        .filter(move |&j| j != first_key && j != second_key)
+                        .flat_map(move |j| [(Some(first_key), j), (Some(second_key), j)]),
+                )
+                .chain(self.first_bigram())
+        };
+        let (old_same_finger_count, old_same_hand_no_roll_count) =
+            self.bigram_counts(&positions, affected_bigrams());
+        let (new_same_finger_count, new_same_hand_no_roll_count) =
+            self.bigram_counts(&swapped_positions, affected_bigrams());
+
+        // The rest of the keys of the fingers stay where they are, only the movements to and from
+        // the presses of the swapped keys change
+        let mut finger_distances = evaluation.finger_distances;
+        let mut fingers = vec![column_finger(first.0), column_finger(second.0)];
+        fingers.dedup();
+        for finger_idx in fingers {
+            let on_finger = |positions: &[(usize, usize); 30], key: usize| {
+                column_finger(positions[key].0) == finger_idx
+            };
+            let others = (0..30)
+                .filter(|&key| key != first_key && key != second_key && on_finger(&positions, key))
+                .collect::<Vec<_>>();
+            let swapped_keys = |positions: &[(usize, usize); 30]| {
+                [first_key, second_key]
+                    .into_iter()
+                    .filter(|&key| on_finger(positions, key))
+                    .collect::<Vec<_>>()
+            };
+            let old_keys = swapped_keys(&positions);
+            let new_keys = swapped_keys(&swapped_positions);
+            finger_distances[finger_idx] +=
+                self.inserted_distance(&swapped_positions, finger_idx, &others, &new_keys)
+                    - self.inserted_distance(&positions, finger_idx, &others, &old_keys);
+        }
+
+        Evaluation {
+            metrics: self.metrics(
+                &swapped_keyboard,
+                &swapped_positions,
+                &finger_distances,
+                evaluation.metrics.same_finger_count + new_same_finger_count
+                    - old_same_finger_count,
+                evaluation.metrics.same_hand_no_roll_count + new_same_hand_no_roll_count
+                    - old_same_hand_no_roll_count,
+            ),
+            positions: swapped_positions,
+            finger_distances,
+        }
+    }
+
+    /// Number of times the key is typed in the input
+    pub fn key_presses(&self, key: char) -> usize {
+        self.occurrences[char_index(key)].len()
+    }
+
+    /// A lower bound of the fitness of every layout that places the missing keys on the empty
+    /// ('-') positions of the keyboard. Key presses and bigrams of missing keys are left out,
+    /// skipping key presses never makes a finger travel further, and each multiplier is the
+    /// lowest it can get with the missing keys on any of the empty positions
+    pub fn lower_bound(&self, keyboard: &Keyboard) -> f64 {
+        let mut placed_positions = [None; 30];
+        let mut empty_positions = Vec::new();
+        for x in 0..10 {
+            for y in 0..3 {
+                match keyboard.keys[x][y] {
+                    '-' => empty_positions.push((x, y)),
+                    key => placed_positions[char_index(key)] = Some((x, y)),
+                }
+            }
+        }
+
+        let is_placed = |key: usize| placed_positions[key].is_some();
+        let positions = placed_positions.map(|position| position.unwrap_or((0, 0)));
+        let finger_keys = |finger_idx: usize| {
+            (0..30).filter(move |&key| {
+                placed_positions[key].is_some_and(|(x, _)| column_finger(x) == finger_idx)
+            })
+        };
+        let distance = (0..10)
+            .map(|finger_idx| self.path_distance(&positions, finger_keys(finger_idx), finger_idx))
+            .sum::<f64>();
+        let (same_finger_count, same_hand_no_roll_count) = self.bigram_counts(
+            &positions,
+            (0..30)
+                .flat_map(|i| (0..30).map(move |j| (Some(i), j)))
+                .chain(self.first_bigram())
+                .filter(|&(prev_key, key)| prev_key.is_none_or(is_placed) && is_placed(key)),
+        );
+
+        // The most a finger or hand can end up with is the usage of the most used missing keys
+        // that fit on its empty positions
+        let most_used_sum = |mut usages: Vec<f64>, count: usize| {
+            usages.sort_unstable_by(|left, right| right.total_cmp(left));
+            usages.into_iter().take(count).sum::<f64>()
+        };
+        let missing_keys = (0..30).filter(|&key| !is_placed(key)).collect::<Vec<_>>();
+        let missing_presses = missing_keys
+            .iter()
+            .map(|&key| self.occurrences[key].len() as f64)
+            .collect::<Vec<_>>();
+        let total_presses = self.occurrences.iter().map(Vec::len).sum::<usize>() as f64;
+        let finger_usage_multiplier = 1.0
+            + (0..10)
+                .map(|finger_idx| {
+                    let usage = finger_keys(finger_idx)
+                        .map(|key| self.occurrences[key].len() as f64)
+                        .sum::<f64>();
+                    let empty_count = empty_positions
+                        .iter()
+                        .filter(|&&(x, _)| column_finger(x) == finger_idx)
+                        .count();
+                    let max_usage = usage + most_used_sum(missing_presses.clone(), empty_count);
+                    finger_usage_penalty(
+                        finger_idx,
+                        FINGER_TARGET_USAGE[finger_idx]
+                            .clamp(usage / total_presses, max_usage / total_presses),
+                    )
+                })
+                .sum::<f64>();
+
+        let left_hand_usage = (0..30)
+            .filter(|&key| placed_positions[key].is_some_and(|(x, _)| x < 5))
+            .map(|key| self.letter_freq.get(&CHARS[key]).unwrap())
+            .sum::<f64>();
+        let missing_freq = missing_keys
+            .iter()
+            .map(|&key| *self.letter_freq.get(&CHARS[key]).unwrap())
+            .collect::<Vec<_>>();
+        let empty_left_count = empty_positions.iter().filter(|&&(x, _)| x < 5).count();
+        let max_left_hand_usage = left_hand_usage + most_used_sum(missing_freq, empty_left_count);
+
+        (distance
+            + same_finger_count as f64 * SAME_FINGER_PENALTY
+            + same_hand_no_roll_count as f64 * SAME_HAND_PENALTY)
+            * finger_usage_multiplier
+            * hand_balance_multiplier(
+                TARGET_LEFT_HAND_USAGE.clamp(left_hand_usage, max_left_hand_usage),
+            )
+            * mouse_shortcut_multiplier(unreachable_shortcut_count(keyboard))
+            * (1.0 + constraints::soft_penalty(keyboard, CONSTRAINTS))
+    }
+
+    fn metrics(
+        &self,
+        keyboard: &Keyboard,
+        positions: &[(usize, usize); 30],
+        finger_distances: &[f64; 10],
+        same_finger_count: usize,
+        same_hand_no_roll_count: usize,
+    ) -> Metrics {
+        let mut finger_usage = [0; 10];
+        for (key, occurrences) in self.occurrences.iter().enumerate() {
+            finger_usage[column_finger(positions[key].0)] += occurrences.len();
+        }
+
+        Metrics {
+            distance: finger_distances.iter().sum(),
+            same_finger_count,
+            same_hand_no_roll_count,
+            finger_usage,
+            left_hand_usage: keyboard.left_hand_usage(self.letter_freq),
+            unreachable_shortcut_count: unreachable_shortcut_count(keyboard),
+            soft_penalty: constraints::soft_penalty(keyboard, CONSTRAINTS),
+        }
+    }
+
+    // Same finger and same hand no roll counts of the bigrams, a bigram without a previous key is
+    // the first key of the input, which is counted as if it followed the first finger like in
+    // `measure`
+    fn bigram_counts(
+        &self,
+        positions: &[(usize, usize); 30],
+        bigrams: impl Iterator<Item = (Option<usize>, usize)>,
+    ) -> (usize, usize) {
+        let mut same_finger_count = 0;
+        let mut same_hand_no_roll_count = 0;
+        let bigrams = bigrams.map(|(prev_key, key)| match prev_key {
+            Some(prev_key) => (Some(prev_key), key, self.bigrams[prev_key][key]),
+            None => (None, key, 1),
+        });
+        for (prev_key, key, count) in bigrams {
+            if count == 0 {
+                continue;
+            }
+
+            let (prev_finger_idx, prev_key) = match prev_key {
+                Some(prev_key) => (column_finger(positions[prev_key].0), CHARS[prev_key]),
+                None => (0, '-'),
+            };
+            let finger_idx = column_finger(positions[key].0);
+            if is_same_finger(prev_finger_idx, prev_key, finger_idx, CHARS[key]) {
+                same_finger_count += count;
+            }
+
+            if is_same_hand_no_roll(prev_finger_idx, finger_idx) {
+                same_hand_no_roll_count += count;
+            }
+        }
+
+        (same_finger_count, same_hand_no_roll_count)
+    }
+
+    fn first_bigram(&self) -> Option<(Option<usize>, usize)> {
+        self.first_key.map(|key| (None, key))
+    }
+
+    // Distance travelled by a finger, going through the key presses of the keys it types in the
+    // order they appear in the input
+    fn finger_distance(&self, positions: &[(usize, usize); 30], finger_idx: usize) -> f64 {
+        self.path_distance(
+            positions,
+            (0..30).filter(|&key| column_finger(positions[key].0) == finger_idx),
+            finger_idx,
+        )
+    }
+
+    // How much further a finger travels when the presses of the inserted keys are added to its
+    // path through the presses of the other keys. Each run of inserted presses between two
+    // consecutive presses of the other keys replaces the movement between those two presses
+    fn inserted_distance(
+        &self,
+        positions: &[(usize, usize); 30],
+        finger_idx: usize,
+        others: &[usize],
+        inserted: &[usize],
+    ) -> f64 {
+        let mut presses = inserted
+            .iter()
+            .flat_map(|&key| self.occurrences[key].iter().map(move |&i| (i, key)))
+            .collect::<Vec<_>>();
+        presses.sort_unstable();
+
+        // The presses of the other keys right before and after an index of the input
+        let neighbours = |i: usize| {
+            let mut prev: Option<(usize, usize)> = None;
+            let mut next: Option<(usize, usize)> = None;
+            for &key in others {
+                let occurrences = &self.occurrences[key];
+                let j = occurrences.partition_point(|&occurrence| occurrence < i);
+                if j > 0 && prev.is_none_or(|(prev_i, _)| occurrences[j - 1] > prev_i) {
+                    prev = Some((occurrences[j - 1], key));
+                }
+
+                if j < occurrences.len() && next.is_none_or(|(next_i, _)| occurrences[j] < next_i) {
+                    next = Some((occurrences[j], key));
+                }
+            }
+
+            (prev, next)
+        };
+        let position = |key: Option<usize>| {
+            key.map_or(FINGER_START_POSITIONS[finger_idx], |key| {
+                (positions[key].0 as isize, positions[key].1 as isize)
+            })
+        };
+        let distance = |(prev_x, prev_y): (isize, isize), (x, y): (isize, isize)| {
+            calc_distance(prev_x, prev_y, x, y)
+        };
+
+        let mut inserted_distance = 0.0;
+        let mut k = 0;
+        while k < presses.len() {
+            let (prev, next) = neighbours(presses[k].0);
+            let start = position(prev.map(|(_, key)| key));
+            let mut from = start;
+            while k < presses.len() && next.is_none_or(|(next_i, _)| presses[k].0 < next_i) {
+                let to = position(Some(presses[k].1));
+                inserted_distance += distance(from, to);
+                from = to;
+                k += 1;
+            }
+
+            if let Some((_, next_key)) = next {
+                let to = position(Some(next_key));
+                inserted_distance += distance(from, to) - distance(start, to);
+            }
+        }
+
+        inserted_distance
+    }
+
+    // Distance travelled by a finger going through the key presses of only the given keys
+    fn path_distance(
+        &self,
+        positions: &[(usize, usize); 30],
+        keys: impl Iterator<Item = usize>,
+        finger_idx: usize,
+    ) -> f64 {
+        let mut cursors = keys.map(|key| (key, 0)).collect::<Vec<_>>();
+        let (mut prev_x, mut prev_y) = FINGER_START_POSITIONS[finger_idx];
+        let mut distance = 0.0;
+        while let Some((key, cursor)) = cursors
+            .iter_mut()
+            .filter(|(key, cursor)| *cursor < self.occurrences[*key].len())
+            .min_by_key(|(key, cursor)| self.occurrences[*key][*cursor])
+        {
+            *cursor += 1;
+            let (x, y) = positions[*key];
+            let (x, y) = (x as isize, y as isize);
+            distance += calc_distance(prev_x, prev_y, x, y);
+            (prev_x, prev_y) = (x, y);
+        }
+
+        distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{evaluate_individual, parse_corpus};
+
+    const CORPUS: &str = "\
+The quick brown fox jumps over the lazy dog.
+Pack my box with five dozen liquor jugs, said the sphinx of black quartz.
+How vexingly quick daft zebras jump while the wizard quickly jinxes the gnomes.
+";
+
+    #[test]
+    fn swaps_match_full_evaluation() {
+        let (input, letter_freq) = parse_corpus(CORPUS, None);
+        let evaluator = DeltaEvaluator::new(&input, &letter_freq);
+        let mut rng = StdRng::seed_from_u64(0);
+        // The keys in alphabetical order, the swaps shuffle them
+        let mut keyboard = Keyboard::default();
+        for (i, &key) in CHARS.iter().enumerate() {
+            keyboard.keys[i / 3][i % 3] = key;
+        }
+
+        let mut evaluation = evaluator.evaluate(&keyboard);
+        for _ in 0..500 {
+            let first = rng.gen_range(0..30);
+            let second = rng.gen_range(0..30);
+            let (first, second) = ((first / 3, first % 3), (second / 3, second % 3));
+            evaluation = evaluator.swap(&keyboard, &evaluation, first, second);
+            keyboard.swap(first, second);
+
+            let fitness = evaluate_individual(&keyboard, &input, &letter_freq);
+            assert!(
+                (evaluation.metrics.fitness() - fitness).abs() <= fitness * 1e-9,
+                "{} after swapping {:?} and {:?}, but {} evaluated in full",
+                evaluation.metrics.fitness(),
+                first,
+                second,
+                fitness
+            );
+        }
+    }
+}
@@ -0,0 +1,450 @@
+pub mod breakdown;
+pub mod constraints;
+pub mod delta;
+pub mod ngrams;
+pub mod physical;
+
+use std::{collections::HashMap, path::Path};
+
+use constraints::{Constraint, Region};
+use kb_layout::Hand;
+use rand::{rngs::StdRng, seq::SliceRandom};
+use serde::Serialize;
+
+/// A layout of the 3x10 grid, the keys of each column from the top row down
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keyboard {
+    pub keys: [[char; 3]; 10],
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self {
+            keys: [['-'; 3]; 10],
+        }
+    }
+}
+
+impl Keyboard {
+    pub fn print(&self) {
+        for y in 0..3 {
+            for x in 0..10 {
+                print!("{} ", self.keys[x][y]);
+            }
+
+            println!();
+        }
+    }
+
+    /// Writes the rows of the layout to a text file, in the format the commands read layout files
+    /// in
+    pub fn save(&self, path: &Path) {
+        let rows = (0..3)
+            .map(|y| {
+                (0..10)
+                    .map(|x| self.keys[x][y].to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+        std::fs::write(path, rows.join("\n") + "\n")
+            .unwrap_or_else(|err| panic!("could not write {}: {}", path.display(), err));
+    }
+
+    pub fn print_freq(&self, letter_freq: &HashMap<char, f64>) {
+        let left_hand_usage = self.left_hand_usage(letter_freq);
+        println!(
+            "Hand usage: {} {} (target: {} {})",
+            left_hand_usage,
+            1.0 - left_hand_usage,
+            TARGET_LEFT_HAND_USAGE,
+            1.0 - TARGET_LEFT_HAND_USAGE
+        );
+        for y in 0..3 {
+            for x in 0..10 {
+                print!("{:.04} ", letter_freq.get(&self.keys[x][y]).unwrap());
+            }
+
+            println!();
+        }
+    }
+
+    pub fn key_pos(&self, key: char) -> (usize, usize) {
+        for x in 0..10 {
+            for y in 0..3 {
+                if self.keys[x][y] == key {
+                    return (x, y);
+                }
+            }
+        }
+
+        unreachable!("{}", key);
+    }
+
+    pub fn key_finger(&self, key: char) -> usize {
+        let (x, _) = self.key_pos(key);
+        column_finger(x)
+    }
+
+    pub fn key_hand(&self, key: char) -> Hand {
+        let (x, _) = self.key_pos(key);
+        if x < 5 {
+            Hand::Left
+        } else {
+            Hand::Right
+        }
+    }
+
+    pub fn left_hand_usage(&self, letter_freq: &HashMap<char, f64>) -> f64 {
+        let mut left_hand_usage = 0.0;
+        for x in 0..5 {
+            for y in 0..3 {
+                left_hand_usage += letter_freq.get(&self.keys[x][y]).unwrap();
+            }
+        }
+
+        left_hand_usage
+    }
+
+    pub fn contains(&self, key: char) -> bool {
+        self.keys.iter().any(|column| column.contains(&key))
+    }
+
+    pub fn swap(&mut self, (x1, y1): (usize, usize), (x2, y2): (usize, usize)) {
+        let key = self.keys[x1][y1];
+        self.keys[x1][y1] = self.keys[x2][y2];
+        self.keys[x2][y2] = key;
+    }
+}
+
+pub const fn column_finger(x: usize) -> usize {
+    match x {
+        4 => 3,
+        5 => 6,
+        n => n,
+    }
+}
+
+pub const CHARS: [char; 30] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
+    't', 'u', 'v', 'w', 'x', 'y', 'z', ',', '.', '?', ';',
+];
+pub const MOST_COMMON_CHARS: [char; 8] = ['e', 't', 'a', 'o', 'i', 'n', 's', 'r'];
+pub const HOME_ROW: Region = Region::row(1)
+    .without(Region::column(4))
+    .without(Region::column(5));
+
+// Settings of the fitness function, shared by the optimizer and the editor. The settings of the
+// search are in the optimizer
+pub const CONSTRAINTS: &[Constraint] = &[
+    Constraint::Hard {
+        keys: &MOST_COMMON_CHARS,
+        region: HOME_ROW,
+    },
+    // Soft constraints multiply the fitness by 1 + penalty times the share of their keys outside
+    // of the region, e.g. to keep the shortcut keys on the bottom left:
+    // Constraint::Soft {
+    //     keys: &['z', 'x', 'c', 'v'],
+    //     region: Region::hand(Hand::Left).and(Region::row(2)),
+    //     penalty: 0.5,
+    // },
+];
+
+// Number of n-grams listed per metric when breaking down the fitness of a layout
+pub const BREAKDOWN_OFFENDERS: usize = 10;
+
+// Lika använding av händer och fingrar för jämt slitage, eller proportioneligt på något vis iallafall.
+// Ta hänsyn till musanvänding, typ dominant hand
+// Fundera på rimlig input att beräkna kostnaden på, typ vilken text/kod. Sampla github?
+// Finger rolling, att använda fingrar brevid för nästa key, behöver kanske mer än en window size på 2 (bigram)
+// Vikt/kostnad för att flytta olika fingrar i olika riktningar
+// flera lager, men det måste kosta mer att använda tummen pga koordination och stoppar flowet
+
+// params (with weights):
+//  * dont use the same finger for two keys in row
+pub const SAME_FINGER_PENALTY: f64 = 2.0;
+
+//  * alternating hands
+pub const SAME_HAND_PENALTY: f64 = 2.0;
+
+//  * disfavour bottom row
+pub const BOTTOM_ROW_PENALTY: f64 = 5.0;
+
+//  * rolls, outer to inner finger mostly, more awkward inner to outer direction
+
+//  * finger strength, i.e. pinky is weaker
+//  * hand/finger usage symmetry symmetry
+pub const FINGER_TARGET_USAGE: [f64; 10] =
+    [0.1, 0.135, 0.135, 0.13, 0.0, 0.0, 0.13, 0.135, 0.135, 0.1];
+
+//  * hand balance, share of key presses typed by the left hand. Left-handed mouse users may want
+//    the right hand to do more of the typing, and vice versa
+pub const TARGET_LEFT_HAND_USAGE: f64 = 0.5;
+
+//  * mouse usage, shortcuts and editing keys used while the other hand is on the mouse should be
+//    reachable by the free hand
+pub const MOUSE_HAND: Option<Hand> = None;
+pub const MOUSE_SHORTCUT_KEYS: [char; 8] = ['z', 'x', 'c', 'v', 'a', 's', 'f', 'w'];
+pub const MOUSE_SHORTCUT_PENALTY: f64 = 0.5;
+
+//  * idle time of fingers
+//  * physical restrictions of fingers in a hand
+//			e.g. one first on top row and the adjacent finger on bottom row on consecutive keys is bad
+pub fn evaluate_individual(
+    individual: &Keyboard,
+    input: &str,
+    letter_freq: &HashMap<char, f64>,
+) -> f64 {
+    measure(individual, input, letter_freq).fitness()
+}
+
+/// Raw measurements of a layout typing the input, the fitness is computed from these
+#[derive(Clone, Serialize)]
+pub struct Metrics {
+    pub distance: f64,
+    pub same_finger_count: usize,
+    pub same_hand_no_roll_count: usize,
+    pub finger_usage: [usize; 10],
+    pub left_hand_usage: f64,
+    pub unreachable_shortcut_count: usize,
+    pub soft_penalty: f64,
+}
+
+impl Metrics {
+    pub fn key_presses(&self) -> usize {
+        self.finger_usage.iter().sum()
+    }
+
+    pub fn finger_usage_multiplier(&self) -> f64 {
+        let total_usage = self.key_presses() as f64;
+        1.0 + self
+            .finger_usage
+            .into_iter()
+            .enumerate()
+            .map(|(finger_idx, usage)| finger_usage_penalty(finger_idx, usage as f64 / total_usage))
+            .sum::<f64>()
+    }
+
+    pub fn hand_balance_multiplier(&self) -> f64 {
+        hand_balance_multiplier(self.left_hand_usage)
+    }
+
+    pub fn mouse_shortcut_multiplier(&self) -> f64 {
+        mouse_shortcut_multiplier(self.unreachable_shortcut_count)
+    }
+
+    /// Costs that are added together, with their weights
+    pub fn terms(&self) -> [(&'static str, f64, f64); 3] {
+        [
+            ("distance", self.distance, 1.0),
+            (
+                "same finger",
+                self.same_finger_count as f64,
+                SAME_FINGER_PENALTY,
+            ),
+            (
+                "same hand",
+                self.same_hand_no_roll_count as f64,
+                SAME_HAND_PENALTY,
+            ),
+        ]
+    }
+
+    /// Factors the sum of the terms is multiplied with
+    pub fn multipliers(&self) -> [(&'static str, f64); 4] {
+        [
+            ("finger usage", self.finger_usage_multiplier()),
+            ("hand balance", self.hand_balance_multiplier()),
+            ("mouse shortcuts", self.mouse_shortcut_multiplier()),
+            ("soft constraints", 1.0 + self.soft_penalty),
+        ]
+    }
+
+    pub fn fitness(&self) -> f64 {
+        let fitness = self
+            .terms()
+            .into_iter()
+            .map(|(_, value, weight)| value * weight)
+            .sum::<f64>();
+        self.multipliers()
+            .into_iter()
+            .fold(fitness, |fitness, (_, multiplier)| fitness * multiplier)
+    }
+}
+
+/// Part of the finger usage multiplier for a finger typing the given share of the key presses
+pub fn finger_usage_penalty(finger_idx: usize, usage: f64) -> f64 {
+    (1.0 + (FINGER_TARGET_USAGE[finger_idx] - usage).abs()).powi(3) - 1.0
+}
+
+pub fn hand_balance_multiplier(left_hand_usage: f64) -> f64 {
+    1.0 + (TARGET_LEFT_HAND_USAGE - left_hand_usage).abs().powi(3)
+}
+
+pub fn mouse_shortcut_multiplier(unreachable_shortcut_count: usize) -> f64 {
+    1.0 + MOUSE_SHORTCUT_PENALTY * unreachable_shortcut_count as f64
+        / MOUSE_SHORTCUT_KEYS.len() as f64
+}
+
+pub const FINGER_START_POSITIONS: [(isize, isize); 10] = [
+    (0, 1),
+    (1, 1),
+    (2, 1),
+    (3, 1),
+    (4, 1),
+    (5, 1),
+    (6, 1),
+    (7, 1),
+    (8, 1),
+    (9, 1),
+];
+
+pub fn calc_distance(prev_x: isize, prev_y: isize, x: isize, y: isize) -> f64 {
+    let top = prev_y == 0 || y == 0;
+    let bottom = prev_y == 2 || y == 2;
+    let x_distance = (prev_x - x).abs() as f64;
+    let y_distance = match (top, bottom) {
+        (true, true) => 1.0 + BOTTOM_ROW_PENALTY,
+        (true, false) => 1.0,
+        (false, true) => BOTTOM_ROW_PENALTY,
+        (false, false) => 0.0,
+    };
+    (x_distance.powi(2) + y_distance.powi(2)).sqrt()
+}
+
+pub fn is_same_finger(
+    prev_finger_idx: usize,
+    prev_key: char,
+    finger_idx: usize,
+    key: char,
+) -> bool {
+    finger_idx == prev_finger_idx && prev_key != key
+}
+
+pub fn is_same_hand_no_roll(prev_finger_idx: usize, finger_idx: usize) -> bool {
+    let same_hand = matches!(
+        (finger_idx, prev_finger_idx),
+        (0..=4, 0..=4) | (5..=9, 5..=9)
+    );
+
+    let normalize_finger_idx = |finger_idx: usize| {
+        if finger_idx > 4 {
+            9 - finger_idx
+        } else {
+            finger_idx
+        }
+    };
+    same_hand && normalize_finger_idx(finger_idx) < normalize_finger_idx(prev_finger_idx)
+}
+
+/// Keys that aren't on the keyboard yet are counted as reachable
+pub fn unreachable_shortcut_count(individual: &Keyboard) -> usize {
+    match MOUSE_HAND {
+        Some(mouse_hand) => MOUSE_SHORTCUT_KEYS
+            .iter()
+            .filter(|&&key| individual.contains(key) && individual.key_hand(key) == mouse_hand)
+            .count(),
+        None => 0,
+    }
+}
+
+pub fn measure(individual: &Keyboard, input: &str, letter_freq: &HashMap<char, f64>) -> Metrics {
+    let mut prev_finger_index = 0;
+    let mut distance = 0.0;
+    let mut finger_positions = FINGER_START_POSITIONS;
+    let mut finger_usage = [0; 10];
+    let mut same_finger_count = 0;
+    let mut same_hand_no_roll_count = 0;
+    let mut prev_key = '-';
+    for key in input.chars() {
+        let finger_idx = individual.key_finger(key);
+        let (x, y) = individual.key_pos(key);
+        let x = x as isize;
+        let y = y as isize;
+
+        let (start_x, start_y) = finger_positions[finger_idx];
+
+        distance += calc_distance(start_x, start_y, x, y);
+        if is_same_finger(prev_finger_index, prev_key, finger_idx, key) {
+            same_finger_count += 1;
+        }
+
+        if is_same_hand_no_roll(prev_finger_index, finger_idx) {
+            same_hand_no_roll_count += 1;
+        }
+
+        finger_positions[finger_idx] = (x, y);
+        finger_usage[finger_idx] += 1;
+        prev_finger_index = finger_idx;
+        prev_key = key;
+    }
+
+    Metrics {
+        distance,
+        same_finger_count,
+        same_hand_no_roll_count,
+        finger_usage,
+        left_hand_usage: individual.left_hand_usage(letter_freq),
+        unreachable_shortcut_count: unreachable_shortcut_count(individual),
+        soft_penalty: constraints::soft_penalty(individual, CONSTRAINTS),
+    }
+}
+
+/// One sentence per line, optionally preceded by a tab separated id like in the Leipzig corpora.
+/// Returns the letters of the sentences, or of a random sample of one in 50 of them drawn with
+/// `sample` when given, along with the frequency of each key over all of them
+pub fn parse_corpus(corpus: &str, sample: Option<&mut StdRng>) -> (String, HashMap<char, f64>) {
+    let mut raw_input = corpus.lines().collect::<Vec<_>>();
+    let step = match sample {
+        Some(rng) => {
+            raw_input.shuffle(rng);
+            50
+        }
+        None => 1,
+    };
+    let input = raw_input
+        .iter()
+        .step_by(step)
+        .map(|line| {
+            let content = line.split_once('\t').map_or(*line, |(_, content)| content);
+            content
+                .to_lowercase()
+                .chars()
+                .filter(|c| c.is_ascii_alphabetic())
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    let mut letter_freq: HashMap<char, f64> = CHARS.iter().map(|&c| (c, 0.0)).collect();
+    let mut bigrams: HashMap<String, f64> = HashMap::new();
+    let mut prev_c = None;
+    for line in raw_input.iter() {
+        for c in line.chars() {
+            let c = c.to_ascii_lowercase();
+            if !CHARS.contains(&c) {
+                continue;
+            }
+
+            *letter_freq.entry(c).or_default() += 1.0;
+
+            if let Some(prev_c) = prev_c {
+                let bigram = format!("{}{}", prev_c, c);
+                *bigrams.entry(bigram).or_default() += 1.0;
+            }
+
+            prev_c = Some(c);
+        }
+    }
+
+    let len: f64 = letter_freq.values().sum();
+    for f in letter_freq.values_mut() {
+        *f /= len;
+    }
+
+    let len: f64 = bigrams.values().sum();
+    for b in bigrams.values_mut() {
+        *b /= len;
+    }
+
+    (input, letter_freq)
+}
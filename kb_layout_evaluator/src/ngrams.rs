@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calc_distance, column_finger, constraints, is_same_finger, is_same_hand_no_roll,
+    unreachable_shortcut_count, Keyboard, Metrics, CONSTRAINTS,
+};
+
+/// A corpus as the number of times each key is typed right after the two keys before it, along
+/// with the frequency of each key. Scoring a layout on the table takes time in the number of
+/// distinct trigrams rather than in the length of the corpus, which the editor needs to score a
+/// layout while a key is dragged
+#[derive(Serialize, Deserialize)]
+pub struct Ngrams {
+    pub letter_freq: HashMap<char, f64>,
+    /// The trigrams ending at each key press of the input, shorter for the first two keys
+    pub trigrams: HashMap<String, usize>,
+}
+
+impl Ngrams {
+    /// The table of the input and key frequencies of `parse_corpus`
+    pub fn count(input: &str, letter_freq: HashMap<char, f64>) -> Self {
+        let keys = input.chars().collect::<Vec<_>>();
+        let mut trigrams = HashMap::new();
+        for end in 1..=keys.len() {
+            let trigram = keys[end.saturating_sub(3)..end].iter().collect::<String>();
+            *trigrams.entry(trigram).or_default() += 1;
+        }
+
+        Self {
+            letter_freq,
+            trigrams,
+        }
+    }
+
+    /// Every key press with the two keys typed before it, '-' before the start of the input, and
+    /// how often it is typed
+    pub fn presses(&self) -> impl Iterator<Item = ([char; 3], usize)> + '_ {
+        self.trigrams.iter().map(|(trigram, &count)| {
+            let mut keys = ['-'; 3];
+            for (key, c) in keys.iter_mut().rev().zip(trigram.chars().rev()) {
+                *key = c;
+            }
+
+            (keys, count)
+        })
+    }
+
+    /// The metrics `measure` gets on the corpus, except for the distance. Only the key typed
+    /// right before a press tells where its finger is, when that key is typed with another
+    /// finger the finger moves from each of its keys as often as they are typed. That is within
+    /// a few percent of the distance of `measure`, which follows the fingers through the corpus
+    pub fn measure(&self, individual: &Keyboard) -> Metrics {
+        let finger_distances = self.finger_distances(individual);
+        let mut distance = 0.0;
+        let mut finger_usage = [0; 10];
+        let mut same_finger_count = 0;
+        let mut same_hand_no_roll_count = 0;
+        for ([_, prev_key, key], count) in self.presses() {
+            let finger_idx = individual.key_finger(key);
+            let prev_finger_idx = finger(individual, prev_key);
+            distance += count as f64 * press_distance(individual, &finger_distances, prev_key, key);
+            if is_same_finger(prev_finger_idx, prev_key, finger_idx, key) {
+                same_finger_count += count;
+            }
+
+            if is_same_hand_no_roll(prev_finger_idx, finger_idx) {
+                same_hand_no_roll_count += count;
+            }
+
+            finger_usage[finger_idx] += count;
+        }
+
+        Metrics {
+            distance,
+            same_finger_count,
+            same_hand_no_roll_count,
+            finger_usage,
+            left_hand_usage: individual.left_hand_usage(&self.letter_freq),
+            unreachable_shortcut_count: unreachable_shortcut_count(individual),
+            soft_penalty: constraints::soft_penalty(individual, CONSTRAINTS),
+        }
+    }
+
+    // How far each finger moves to each position on average, from its keys weighted by their
+    // frequency
+    pub(crate) fn finger_distances(&self, individual: &Keyboard) -> [[f64; 30]; 10] {
+        let mut distances = [[0.0; 30]; 10];
+        let mut weights = [0.0; 10];
+        for i in 0..30 {
+            let (prev_x, prev_y) = (i / 3, i % 3);
+            let finger_idx = column_finger(prev_x);
+            let weight = self
+                .letter_freq
+                .get(&individual.keys[prev_x][prev_y])
+                .copied()
+                .unwrap_or(0.0);
+            weights[finger_idx] += weight;
+            for (j, distance) in distances[finger_idx].iter_mut().enumerate() {
+                *distance += weight
+                    * calc_distance(
+                        prev_x as isize,
+                        prev_y as isize,
+                        (j / 3) as isize,
+                        (j % 3) as isize,
+                    );
+            }
+        }
+
+        for (distances, weight) in distances.iter_mut().zip(weights) {
+            if weight > 0.0 {
+                distances
+                    .iter_mut()
+                    .for_each(|distance| *distance /= weight);
+            }
+        }
+
+        distances
+    }
+}
+
+// The finger of a key, the first finger of the left hand before the start of the input like in
+// `measure`
+pub(crate) fn finger(individual: &Keyboard, key: char) -> usize {
+    if key == '-' {
+        0
+    } else {
+        individual.key_finger(key)
+    }
+}
+
+// How far the finger of `key` moves to type it after `prev_key`, see `Ngrams::measure`
+pub(crate) fn press_distance(
+    individual: &Keyboard,
+    finger_distances: &[[f64; 30]; 10],
+    prev_key: char,
+    key: char,
+) -> f64 {
+    let finger_idx = individual.key_finger(key);
+    let (x, y) = individual.key_pos(key);
+    if prev_key != '-' && individual.key_finger(prev_key) == finger_idx {
+        let (prev_x, prev_y) = individual.key_pos(prev_key);
+        calc_distance(prev_x as isize, prev_y as isize, x as isize, y as isize)
+    } else {
+        finger_distances[finger_idx][x * 3 + y]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{breakdown, measure, parse_corpus, CHARS};
+
+    const CORPUS: &str = "\
+The quick brown fox jumps over the lazy dog.
+Pack my box with five dozen liquor jugs, said the sphinx of black quartz.
+How vexingly quick daft zebras jump while the wizard quickly jinxes the gnomes.
+";
+
+    // The keys in alphabetical order
+    fn alphabetical() -> Keyboard {
+        let mut keyboard = Keyboard::default();
+        for (i, &key) in CHARS.iter().enumerate() {
+            keyboard.keys[i / 3][i % 3] = key;
+        }
+
+        keyboard
+    }
+
+    #[test]
+    fn counts_match_measure() {
+        let (input, letter_freq) = parse_corpus(CORPUS, None);
+        let ngrams = Ngrams::count(&input, letter_freq.clone());
+        let keyboard = alphabetical();
+        let metrics = measure(&keyboard, &input, &letter_freq);
+        let table_metrics = ngrams.measure(&keyboard);
+        assert_eq!(table_metrics.finger_usage, metrics.finger_usage);
+        assert_eq!(table_metrics.same_finger_count, metrics.same_finger_count);
+        assert_eq!(
+            table_metrics.same_hand_no_roll_count,
+            metrics.same_hand_no_roll_count
+        );
+        assert_eq!(table_metrics.left_hand_usage, metrics.left_hand_usage);
+    }
+
+    #[test]
+    fn offenders_match_breakdown() {
+        let (input, letter_freq) = parse_corpus(CORPUS, None);
+        let ngrams = Ngrams::count(&input, letter_freq.clone());
+        let keyboard = alphabetical();
+        let offenders = |breakdown: breakdown::Breakdown| {
+            breakdown
+                .offenders
+                .into_iter()
+                .skip(1)
+                .flat_map(|offenders| offenders.bigrams.into_iter().chain(offenders.trigrams))
+                .map(|ngram| (ngram.keys, ngram.count))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            offenders(breakdown::ngram_breakdown(&keyboard, &ngrams)),
+            offenders(breakdown::breakdown(&keyboard, &input, &letter_freq))
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use kb_layout::board::{self, Board};
+use kb_layout::{Button, Finger, Hand, Key, Layer, Layout, MatrixPosition, Position};
+
+use crate::{calc_distance, column_finger, Keyboard, CHARS, FINGER_START_POSITIONS};
+
+// Space between the halves, in key units
+const SPLIT_GAP: f64 = 0.5;
+
+/// The 3x10 grid the optimizer places keys on, with a button per position in the order of the
+/// flattened layout (x * 3 + y)
+pub fn keyboard() -> kb_layout::Keyboard {
+    let mut buttons = Vec::with_capacity(30);
+    for x in 0..10 {
+        for y in 0..3 {
+            buttons.push(grid_button(x as isize, y as isize, x as u8));
+        }
+    }
+
+    kb_layout::Keyboard { buttons }
+}
+
+/// A keyboard with rows of the given lengths, like 12,12,12,6 for a split board with three
+/// thumb keys per hand, with buttons in row-major order. The middle ten keys of the first three
+/// rows are the 3x10 grid, the keys beside them are typed with the pinkies and the keys of the
+/// rows below with the thumbs
+pub fn grid_keyboard(row_lengths: &[usize]) -> kb_layout::Keyboard {
+    let mut buttons = Vec::new();
+    for (y, &length) in row_lengths.iter().enumerate() {
+        // Column of the first key of the row on the grid, negative when the row is wider
+        let first_x = -(length as isize - 10) / 2;
+        for col in 0..length {
+            let x = first_x + col as isize;
+            if y < 3 {
+                buttons.push(grid_button(x, y as isize, col as u8));
+                continue;
+            }
+
+            let left = col < length / 2;
+            let home_col = if left { length / 2 - 1 } else { length / 2 };
+            let home_offset = Position {
+                x: col as f64 - home_col as f64,
+                y: 0.0,
+            };
+            buttons.push(Button {
+                hand: if left { Hand::Left } else { Hand::Right },
+                finger: Finger::Thumb,
+                position: Position {
+                    x: x as f64 + if left { 0.0 } else { SPLIT_GAP },
+                    y: y as f64,
+                },
+                matrix_position: MatrixPosition {
+                    col: col as u8,
+                    row: y as u8,
+                },
+                home_offset,
+                cost: home_offset.x.abs(),
+            });
+        }
+    }
+
+    kb_layout::Keyboard { buttons }
+}
+
+// The button at a position of the grid, positions left and right of it are typed with the
+// pinkies
+fn grid_button(x: isize, y: isize, col: u8) -> Button {
+    let finger_idx = column_finger(x.clamp(0, 9) as usize);
+    let (home_x, home_y) = FINGER_START_POSITIONS[finger_idx];
+    let left = x < 5;
+    Button {
+        hand: if left { Hand::Left } else { Hand::Right },
+        finger: match finger_idx {
+            0 | 9 => Finger::Pinky,
+            1 | 8 => Finger::Ring,
+            2 | 7 => Finger::Middle,
+            3 | 6 => Finger::Pointer,
+            _ => Finger::Thumb,
+        },
+        position: Position {
+            x: x as f64 + if left { 0.0 } else { SPLIT_GAP },
+            y: y as f64,
+        },
+        matrix_position: MatrixPosition { col, row: y as u8 },
+        home_offset: Position {
+            x: (x - home_x) as f64,
+            y: (y - home_y) as f64,
+        },
+        cost: calc_distance(home_x, home_y, x, y),
+    }
+}
+
+/// The keys of the layout on the buttons of `keyboard()`, as a single layer. The ? of the grid
+/// types / and ? with shift, like on `board_layout`
+pub fn layout(keyboard: &Keyboard) -> Layout {
+    let keys = (0..30)
+        .map(|i| grid_key(keyboard.keys[i / 3][i % 3]))
+        .collect();
+    Layout {
+        layers: vec![Layer {
+            name: "base".to_string(),
+            keys,
+        }],
+    }
+}
+
+/// The layout on an ANSI or ISO board, with the 3x10 grid on the letter keys and the keys around
+/// them left as they are on the US layout. The ? of the grid types / and ? with shift, like the
+/// US key it stands for
+pub fn board_layout(keyboard: &Keyboard, board: Board) -> Layout {
+    let keys = board
+        .keyboard()
+        .buttons
+        .iter()
+        .map(|button| {
+            let (col, row) = (
+                button.matrix_position.col as usize,
+                button.matrix_position.row as usize,
+            );
+            if (1..=10).contains(&col) && (1..=3).contains(&row) {
+                grid_key(keyboard.keys[col - 1][row - 1])
+            } else {
+                board::us_char(button.matrix_position).map_or(Key::None, Key::Char)
+            }
+        })
+        .collect();
+    Layout {
+        layers: vec![Layer {
+            name: "base".to_string(),
+            keys,
+        }],
+    }
+}
+
+// The key a character of the grid is put on, ? is typed with shift on the / key
+fn grid_key(c: char) -> Key {
+    match c {
+        '?' => Key::Char('/'),
+        c => Key::Char(c),
+    }
+}
+
+/// The 3x10 grid of the first layer of a layout: the middle ten keys of the first three matrix
+/// rows. Mod-taps and layer-taps count as the key they tap. Symbols are read as the key of the
+/// grid on the same US key, like '/' for '?', and the grid has to end up with every key of the
+/// optimizer once
+pub fn grid(keyboard: &kb_layout::Keyboard, layout: &Layout) -> Result<Keyboard, String> {
+    let layer = layout.layers.first().ok_or("the layout has no layers")?;
+    let rows = keyboard.matrix_rows();
+    if rows.len() < 3 {
+        return Err(format!("the keyboard has {} rows, not 3", rows.len()));
+    }
+
+    let mut grid = Keyboard::default();
+    for (y, row) in rows.iter().take(3).enumerate() {
+        if row.len() < 10 {
+            return Err(format!("row {} has {} keys, not 10", y, row.len()));
+        }
+
+        let first = (row.len() - 10) / 2;
+        for (x, &i) in row[first..first + 10].iter().enumerate() {
+            let key = match &layer.keys[i] {
+                Key::LayerTap { tap, .. } | Key::ModTap { tap, .. } => tap,
+                key => key,
+            };
+            grid.keys[x][y] = match key {
+                Key::Char(c) => {
+                    grid_char(*c).ok_or_else(|| format!("{} is not a key of the optimizer", c))?
+                }
+                key => return Err(format!("{} is not a key of the optimizer", key.legend())),
+            };
+        }
+    }
+
+    if let Some(missing) = CHARS.iter().find(|&&c| !grid.contains(c)) {
+        return Err(format!(
+            "the layout has no {} key in the 3x10 grid",
+            missing
+        ));
+    }
+
+    Ok(grid)
+}
+
+// The key of the grid that types the character, with or without shift
+fn grid_char(c: char) -> Option<char> {
+    let c = c.to_lowercase().next()?;
+    [c, board::shifted(c)]
+        .into_iter()
+        .chain(
+            CHARS
+                .iter()
+                .copied()
+                .filter(|&key| board::shifted(key) == c),
+        )
+        .find(|key| CHARS.contains(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use kb_layout::{qmk, zmk};
+
+    use super::*;
+
+    // The keys in alphabetical order
+    fn alphabetical() -> Keyboard {
+        let mut keyboard = Keyboard::default();
+        for (i, &key) in CHARS.iter().enumerate() {
+            keyboard.keys[i / 3][i % 3] = key;
+        }
+
+        keyboard
+    }
+
+    #[test]
+    fn qmk_types_question_mark_with_shift() {
+        let keymap = qmk::keymap_c(&keyboard(), &layout(&alphabetical()), "LAYOUT").unwrap();
+        assert!(keymap.contains("KC_SLSH"));
+        assert!(!keymap.contains("KC_QUES"));
+    }
+
+    #[test]
+    fn zmk_types_question_mark_with_shift() {
+        let keymap = zmk::keymap(&keyboard(), &layout(&alphabetical())).unwrap();
+        assert!(keymap.contains("&kp FSLH"));
+        assert!(!keymap.contains("QMARK"));
+    }
+
+    #[test]
+    fn grid_round_trips() {
+        let grid = grid(&keyboard(), &layout(&alphabetical())).unwrap();
+        assert_eq!(grid.keys, alphabetical().keys);
+    }
+}
@@ -0,0 +1,112 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+
+use kb_layout_evaluator::{
+    constraints::{self, Constraint},
+    delta::DeltaEvaluator,
+    Keyboard,
+};
+
+use crate::{genetic, ANNEALING_COOLING, ANNEALING_ITERATIONS, ANNEALING_RESTARTS};
+
+#[derive(Clone, Copy, Debug)]
+// Not every schedule is used by the default settings
+#[allow(dead_code)]
+pub enum CoolingSchedule {
+    /// The temperature is multiplied by a constant factor each iteration
+    Exponential { start: f64, end: f64 },
+    /// The temperature is decreased by a constant amount each iteration
+    Linear { start: f64, end: f64 },
+}
+
+impl CoolingSchedule {
+    // Progress goes from 0.0 at the first iteration to 1.0 at the last
+    fn temperature(self, progress: f64) -> f64 {
+        match self {
+            Self::Exponential { start, end } => start * (end / start).powf(progress),
+            Self::Linear { start, end } => start + (end - start) * progress,
+        }
+    }
+}
+
+/// Anneals ANNEALING_RESTARTS random layouts in parallel and returns the best layout found along
+/// with its fitness, lower fitness is better. Each restart gets its own RNG seeded from `rng`
+pub fn run(
+    constraints: &[Constraint],
+    evaluator: &DeltaEvaluator,
+    rng: &mut StdRng,
+) -> (Keyboard, f64) {
+    let restart_seeds = (0..ANNEALING_RESTARTS)
+        .map(|_| rng.gen())
+        .collect::<Vec<u64>>();
+    restart_seeds
+        .into_par_iter()
+        .enumerate()
+        .map(|(restart, seed)| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (keyboard, fitness) = anneal(
+                genetic::generate_individual(constraints, &mut rng),
+                constraints,
+                evaluator,
+                &mut rng,
+            );
+            println!("[{}] best: {:.1}", restart, fitness);
+            (keyboard, fitness)
+        })
+        .min_by(|(_, left), (_, right)| {
+            left.partial_cmp(right).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap()
+}
+
+fn anneal(
+    mut current: Keyboard,
+    constraints: &[Constraint],
+    evaluator: &DeltaEvaluator,
+    rng: &mut StdRng,
+) -> (Keyboard, f64) {
+    let mut evaluation = evaluator.evaluate(&current);
+    let mut current_fitness = evaluation.metrics.fitness();
+    let mut best = (current.clone(), current_fitness);
+    for i in 0..ANNEALING_ITERATIONS {
+        let Some((first, second)) = random_swap(&current, constraints, rng) else {
+            break;
+        };
+
+        // The temperature is relative to the current fitness so that it doesn't depend on the
+        // size of the input
+        let swapped = evaluator.swap(&current, &evaluation, first, second);
+        let swapped_fitness = swapped.metrics.fitness();
+        let relative_delta = (swapped_fitness - current_fitness) / current_fitness;
+        let temperature = ANNEALING_COOLING.temperature(i as f64 / ANNEALING_ITERATIONS as f64);
+        if relative_delta <= 0.0 || rng.gen::<f64>() < (-relative_delta / temperature).exp() {
+            evaluation = swapped;
+            current.swap(first, second);
+            current_fitness = swapped_fitness;
+            if current_fitness < best.1 {
+                best = (current.clone(), current_fitness);
+            }
+        }
+    }
+
+    best
+}
+
+// A random pair of positions whose keys can be swapped without breaking any hard constraint
+fn random_swap(
+    keyboard: &Keyboard,
+    constraints: &[Constraint],
+    rng: &mut StdRng,
+) -> Option<((usize, usize), (usize, usize))> {
+    for _ in 0..1000 {
+        let first = rng.gen::<usize>() % 30;
+        let second = rng.gen::<usize>() % 30;
+        let first = (first / 3, first % 3);
+        let second = (second / 3, second % 3);
+        if first != second && constraints::can_swap(keyboard, first, second, constraints) {
+            return Some((first, second));
+        }
+    }
+
+    None
+}
@@ -0,0 +1,183 @@
+use std::fmt::Write;
+
+use clap::ValueEnum;
+
+use kb_layout_evaluator::Metrics;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TableFormat {
+    /// Columns aligned with spaces
+    Text,
+    Markdown,
+    Csv,
+}
+
+/// The metrics of one layout on one corpus
+pub struct Row {
+    pub corpus: String,
+    pub layout: String,
+    pub metrics: Metrics,
+}
+
+// Name of a metric, the number of decimals to show it with and how to get it
+type Column = (&'static str, usize, fn(&Metrics) -> f64);
+
+const COLUMNS: &[Column] = &[
+    ("fitness", 1, |metrics| metrics.fitness()),
+    ("distance", 1, |metrics| metrics.distance),
+    ("same finger", 0, |metrics| metrics.same_finger_count as f64),
+    ("same hand", 0, |metrics| {
+        metrics.same_hand_no_roll_count as f64
+    }),
+    ("finger usage", 4, |metrics| {
+        metrics.finger_usage_multiplier()
+    }),
+    ("left hand", 4, |metrics| metrics.left_hand_usage),
+    ("hand balance", 4, |metrics| {
+        metrics.hand_balance_multiplier()
+    }),
+    ("mouse shortcuts", 4, |metrics| {
+        metrics.mouse_shortcut_multiplier()
+    }),
+    ("soft penalty", 4, |metrics| metrics.soft_penalty),
+];
+
+/// Renders the rows as a table with a column for every metric. Rows are grouped by corpus and
+/// ranked by fitness within each corpus, and every metric is followed by its difference in percent
+/// from the baseline layout on the same corpus
+pub fn render(rows: &[Row], baseline: &str, format: TableFormat) -> String {
+    let mut corpora = Vec::new();
+    for row in rows {
+        if !corpora.contains(&&row.corpus) {
+            corpora.push(&row.corpus);
+        }
+    }
+
+    let mut header = vec![
+        "corpus".to_string(),
+        "rank".to_string(),
+        "layout".to_string(),
+    ];
+    for (name, _, _) in COLUMNS {
+        header.push(name.to_string());
+        if matches!(format, TableFormat::Csv) {
+            header.push(format!("{} delta %", name));
+        }
+    }
+
+    let mut table = vec![header];
+    for corpus in corpora {
+        let mut corpus_rows = rows
+            .iter()
+            .filter(|row| &row.corpus == corpus)
+            .collect::<Vec<_>>();
+        corpus_rows.sort_by(|left, right| {
+            left.metrics
+                .fitness()
+                .partial_cmp(&right.metrics.fitness())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let baseline_metrics = corpus_rows
+            .iter()
+            .find(|row| row.layout == baseline)
+            .map(|row| &row.metrics);
+
+        for (rank, row) in corpus_rows.iter().enumerate() {
+            let mut cells = vec![
+                row.corpus.clone(),
+                (rank + 1).to_string(),
+                row.layout.clone(),
+            ];
+            for (_, decimals, value) in COLUMNS {
+                let delta = baseline_metrics
+                    .map(value)
+                    .filter(|&baseline_value| baseline_value != 0.0)
+                    .map(|baseline_value| {
+                        100.0 * (value(&row.metrics) - baseline_value) / baseline_value
+                    });
+                match format {
+                    TableFormat::Csv => {
+                        cells.push(value(&row.metrics).to_string());
+                        cells.push(delta.map_or(String::new(), |delta| delta.to_string()));
+                    }
+                    TableFormat::Text | TableFormat::Markdown => cells.push(format!(
+                        "{:.decimals$} ({})",
+                        value(&row.metrics),
+                        delta.map_or("-".to_string(), |delta| format!("{:+.1}%", delta))
+                    )),
+                }
+            }
+
+            table.push(cells);
+        }
+    }
+
+    match format {
+        TableFormat::Text => render_text(&table),
+        TableFormat::Markdown => render_markdown(&table),
+        TableFormat::Csv => render_csv(&table),
+    }
+}
+
+fn column_widths(table: &[Vec<String>]) -> Vec<usize> {
+    (0..table[0].len())
+        .map(|i| table.iter().map(|row| row[i].len()).max().unwrap())
+        .collect()
+}
+
+fn render_text(table: &[Vec<String>]) -> String {
+    let widths = column_widths(table);
+    let mut output = String::new();
+    for row in table {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(output, "{}", line.trim_end()).unwrap();
+    }
+
+    output
+}
+
+fn render_markdown(table: &[Vec<String>]) -> String {
+    let widths = column_widths(table);
+    let mut output = String::new();
+    for (i, row) in table.iter().enumerate() {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell.replace('|', "\\|"), width = width))
+            .collect::<Vec<_>>();
+        writeln!(output, "| {} |", cells.join(" | ")).unwrap();
+        if i == 0 {
+            let separators = widths
+                .iter()
+                .map(|&width| "-".repeat(width.max(3)))
+                .collect::<Vec<_>>();
+            writeln!(output, "| {} |", separators.join(" | ")).unwrap();
+        }
+    }
+
+    output
+}
+
+fn render_csv(table: &[Vec<String>]) -> String {
+    let mut output = String::new();
+    for row in table {
+        let cells = row
+            .iter()
+            .map(|cell| {
+                if cell.contains([',', '"', '\n']) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        writeln!(output, "{}", cells.join(",")).unwrap();
+    }
+
+    output
+}
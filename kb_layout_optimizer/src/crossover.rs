@@ -0,0 +1,344 @@
+use std::ops::Range;
+
+use rand::{rngs::StdRng, Rng};
+
+use kb_layout_evaluator::{
+    constraints::{self, Constraint, Region},
+    Keyboard, CHARS,
+};
+
+#[derive(Clone, Copy, Debug)]
+// Not every operator is used by the default settings
+#[allow(dead_code)]
+pub enum CrossoverOperator {
+    /// Keeps the keys before a random point from one parent and fills in the rest from the other
+    /// parent where possible, the remaining keys are placed at random
+    SinglePoint,
+    /// PMX, keeps a random segment from one parent and places the other parent's keys outside of
+    /// it, following the mapping between the parents' segments on conflicts
+    PartiallyMapped,
+    /// OX, keeps a random segment from one parent and fills in the rest in the order the keys
+    /// appear in the other parent
+    Order,
+    /// CX, every key stays on the position it has in one of the parents, alternating parent
+    /// for each cycle of positions
+    Cycle,
+    /// Keeps the keys on random positions from one parent and fills in the rest in the order the
+    /// keys appear in the other parent
+    PositionBased,
+}
+
+impl CrossoverOperator {
+    // One child of the two permutations of the same keys, taking after the first parent
+    fn child(self, first: &[char], second: &[char], rng: &mut StdRng) -> Vec<char> {
+        match self {
+            Self::SinglePoint => {
+                unreachable!("single point crossover isn't a permutation operator")
+            }
+            Self::PartiallyMapped => partially_mapped(first, second, random_segment(first, rng)),
+            Self::Order => order(first, second, random_segment(first, rng)),
+            Self::Cycle => cycle(first, second),
+            Self::PositionBased => {
+                let kept = (0..first.len())
+                    .map(|_| rng.gen::<bool>())
+                    .collect::<Vec<_>>();
+                position_based(first, second, &kept)
+            }
+        }
+    }
+}
+
+pub fn cross(
+    first_individual: &Keyboard,
+    second_individual: &Keyboard,
+    operator: CrossoverOperator,
+    constraints: &[Constraint],
+    rng: &mut StdRng,
+) -> (Keyboard, Keyboard) {
+    let (first_new_individual, second_new_individual) = match operator {
+        CrossoverOperator::SinglePoint => {
+            let crossover_point = rng.gen::<usize>() % 30;
+            (
+                cross_single_point(
+                    first_individual,
+                    second_individual,
+                    0..crossover_point,
+                    constraints,
+                    rng,
+                ),
+                cross_single_point(
+                    second_individual,
+                    first_individual,
+                    crossover_point..30,
+                    constraints,
+                    rng,
+                ),
+            )
+        }
+        _ => (
+            cross_permutation(
+                first_individual,
+                second_individual,
+                operator,
+                constraints,
+                rng,
+            ),
+            cross_permutation(
+                second_individual,
+                first_individual,
+                operator,
+                constraints,
+                rng,
+            ),
+        ),
+    };
+
+    (
+        first_new_individual.unwrap_or_else(|| first_individual.clone()),
+        second_new_individual.unwrap_or_else(|| second_individual.clone()),
+    )
+}
+
+// Takes the keys within the range from the first individual and as many as possible of the rest
+// from the second individual, keys that are still missing are filled in at random
+fn cross_single_point(
+    first_individual: &Keyboard,
+    second_individual: &Keyboard,
+    range: Range<usize>,
+    constraints: &[Constraint],
+    rng: &mut StdRng,
+) -> Option<Keyboard> {
+    let mut new_individual = Keyboard::default();
+    for i in range.clone() {
+        new_individual.keys[i / 3][i % 3] = first_individual.keys[i / 3][i % 3];
+    }
+
+    for i in (0..30).filter(|i| !range.contains(i)) {
+        let key = second_individual.keys[i / 3][i % 3];
+        if !new_individual.contains(key) {
+            new_individual.keys[i / 3][i % 3] = key;
+        }
+    }
+
+    let missing_chars = CHARS
+        .into_iter()
+        .filter(|&key| !new_individual.contains(key))
+        .collect();
+    constraints::fill(&mut new_individual, missing_chars, constraints, rng)
+        .then_some(new_individual)
+}
+
+// Applies the operator separately to each class of positions, where a class is the positions
+// whose keys share the same allowed region, e.g. the home row keys when MOST_COMMON_CHARS are
+// constrained to it. This keeps every key within its region as long as both parents agree on the
+// classes, otherwise the operator is applied to the whole layout and the keys that end up outside
+// of their regions are placed again at random
+fn cross_permutation(
+    first_individual: &Keyboard,
+    second_individual: &Keyboard,
+    operator: CrossoverOperator,
+    constraints: &[Constraint],
+    rng: &mut StdRng,
+) -> Option<Keyboard> {
+    let first = flatten(first_individual);
+    let second = flatten(second_individual);
+    let region = |keys: &[char; 30], i: usize| constraints::allowed_region(keys[i], constraints);
+    let classes = if (0..30).all(|i| region(&first, i) == region(&second, i)) {
+        let mut classes: Vec<(Region, Vec<usize>)> = Vec::new();
+        for i in 0..30 {
+            match classes
+                .iter_mut()
+                .find(|(class, _)| *class == region(&first, i))
+            {
+                Some((_, positions)) => positions.push(i),
+                None => classes.push((region(&first, i), vec![i])),
+            }
+        }
+
+        classes
+            .into_iter()
+            .map(|(_, positions)| positions)
+            .collect()
+    } else {
+        vec![(0..30).collect::<Vec<_>>()]
+    };
+
+    let mut new_individual = Keyboard::default();
+    for positions in classes {
+        let first_keys = positions.iter().map(|&i| first[i]).collect::<Vec<_>>();
+        let second_keys = positions.iter().map(|&i| second[i]).collect::<Vec<_>>();
+        let child = operator.child(&first_keys, &second_keys, rng);
+        for (i, key) in positions.into_iter().zip(child) {
+            if constraints::can_place(key, i / 3, i % 3, constraints) {
+                new_individual.keys[i / 3][i % 3] = key;
+            }
+        }
+    }
+
+    let missing_chars = CHARS
+        .into_iter()
+        .filter(|&key| !new_individual.contains(key))
+        .collect();
+    constraints::fill(&mut new_individual, missing_chars, constraints, rng)
+        .then_some(new_individual)
+}
+
+fn flatten(keyboard: &Keyboard) -> [char; 30] {
+    let mut keys = ['-'; 30];
+    for (i, key) in keys.iter_mut().enumerate() {
+        *key = keyboard.keys[i / 3][i % 3];
+    }
+
+    keys
+}
+
+fn random_segment(keys: &[char], rng: &mut StdRng) -> Range<usize> {
+    let mut points = [rng.gen_range(0..=keys.len()), rng.gen_range(0..=keys.len())];
+    points.sort_unstable();
+    points[0]..points[1]
+}
+
+fn partially_mapped(first: &[char], second: &[char], segment: Range<usize>) -> Vec<char> {
+    let mut child = second.to_vec();
+    child[segment.clone()].copy_from_slice(&first[segment.clone()]);
+    for i in (0..first.len()).filter(|i| !segment.contains(i)) {
+        let mut key = second[i];
+        // Follow the mapping until the key isn't one of the keys taken from the segment
+        while let Some(j) = first[segment.clone()].iter().position(|&k| k == key) {
+            key = second[segment.start + j];
+        }
+
+        child[i] = key;
+    }
+
+    child
+}
+
+fn order(first: &[char], second: &[char], segment: Range<usize>) -> Vec<char> {
+    let len = first.len();
+    let mut child = vec!['-'; len];
+    child[segment.clone()].copy_from_slice(&first[segment.clone()]);
+    let mut remaining_keys = (0..len)
+        .map(|i| second[(segment.end + i) % len])
+        .filter(|key| !first[segment.clone()].contains(key));
+    for i in (0..len).map(|i| (segment.end + i) % len) {
+        if !segment.contains(&i) {
+            child[i] = remaining_keys.next().unwrap();
+        }
+    }
+
+    child
+}
+
+fn cycle(first: &[char], second: &[char]) -> Vec<char> {
+    let mut child = vec!['-'; first.len()];
+    let mut from_first = true;
+    for start in 0..first.len() {
+        if child[start] != '-' {
+            continue;
+        }
+
+        let mut i = start;
+        loop {
+            child[i] = if from_first { first[i] } else { second[i] };
+            i = first.iter().position(|&key| key == second[i]).unwrap();
+            if i == start {
+                break;
+            }
+        }
+
+        from_first = !from_first;
+    }
+
+    child
+}
+
+fn position_based(first: &[char], second: &[char], kept: &[bool]) -> Vec<char> {
+    let kept_keys = (0..first.len())
+        .filter(|&i| kept[i])
+        .map(|i| first[i])
+        .collect::<Vec<_>>();
+    let mut remaining_keys = second.iter().filter(|key| !kept_keys.contains(key));
+    (0..first.len())
+        .map(|i| {
+            if kept[i] {
+                first[i]
+            } else {
+                *remaining_keys.next().unwrap()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::genetic;
+    use kb_layout_evaluator::{CONSTRAINTS, HOME_ROW, MOST_COMMON_CHARS};
+
+    const OPERATORS: [CrossoverOperator; 4] = [
+        CrossoverOperator::PartiallyMapped,
+        CrossoverOperator::Order,
+        CrossoverOperator::Cycle,
+        CrossoverOperator::PositionBased,
+    ];
+    // The default constraints along with a pinned key and keys in a region larger than them, so
+    // that the parents usually disagree on the classes of positions
+    const MORE_CONSTRAINTS: &[Constraint] = &[
+        Constraint::Hard {
+            keys: &MOST_COMMON_CHARS,
+            region: HOME_ROW,
+        },
+        Constraint::pin(&['q'], 0, 0),
+        Constraint::Hard {
+            keys: &['z', 'x'],
+            region: Region::row(2),
+        },
+    ];
+
+    fn sorted(keys: &[char]) -> Vec<char> {
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn children_are_permutations() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let first = CHARS.to_vec();
+            let mut second = CHARS.to_vec();
+            second.shuffle(&mut rng);
+            for operator in OPERATORS {
+                let child = operator.child(&first, &second, &mut rng);
+                assert_eq!(sorted(&child), sorted(&CHARS), "{:?}", operator);
+            }
+        }
+    }
+
+    #[test]
+    fn children_keep_hard_constraints() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for constraints in [CONSTRAINTS, MORE_CONSTRAINTS] {
+            for _ in 0..100 {
+                let first = genetic::generate_individual(constraints, &mut rng);
+                let second = genetic::generate_individual(constraints, &mut rng);
+                for operator in OPERATORS {
+                    let (first_child, second_child) =
+                        cross(&first, &second, operator, constraints, &mut rng);
+                    for child in [first_child, second_child] {
+                        assert_eq!(sorted(&flatten(&child)), sorted(&CHARS), "{:?}", operator);
+                        assert!(
+                            constraints::is_satisfied(&child, constraints),
+                            "{:?} broke the constraints: {:?}",
+                            operator,
+                            child
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use kb_layout_evaluator::Keyboard;
+
+/// Share of positions at which two individuals of the population differ, averaged over every
+/// pair of individuals. 0.0 when all individuals are the same layout
+pub fn pairwise_distance(population: &[Keyboard]) -> f64 {
+    if population.len() < 2 {
+        return 0.0;
+    }
+
+    // Pairs that agree on a position are the pairs among the individuals with the same key there
+    let pairs = (population.len() * (population.len() - 1)) as f64;
+    let mut distance = 0.0;
+    for x in 0..10 {
+        for y in 0..3 {
+            let mut counts = HashMap::<char, usize>::new();
+            for individual in population {
+                *counts.entry(individual.keys[x][y]).or_insert(0) += 1;
+            }
+
+            let agreeing_pairs = counts.values().map(|&count| count * (count - 1));
+            distance += 1.0 - agreeing_pairs.sum::<usize>() as f64 / pairs;
+        }
+    }
+
+    distance / 30.0
+}
+
+/// Shannon entropy in bits of the keys found at each position across the population, indexed
+/// like the flattened layout. 0.0 where every individual has the same key
+pub fn position_entropy(population: &[Keyboard]) -> [f64; 30] {
+    let mut entropy = [0.0; 30];
+    for (i, entropy) in entropy.iter_mut().enumerate() {
+        let mut counts = HashMap::<char, usize>::new();
+        for individual in population {
+            *counts.entry(individual.keys[i / 3][i % 3]).or_insert(0) += 1;
+        }
+
+        *entropy = counts
+            .values()
+            .map(|&count| count as f64 / population.len() as f64)
+            .fold(0.0, |entropy, share| entropy - share * share.log2());
+    }
+
+    entropy
+}
+
+/// Entropy per position averaged over the positions
+pub fn mean_entropy(population: &[Keyboard]) -> f64 {
+    position_entropy(population).iter().sum::<f64>() / 30.0
+}
@@ -0,0 +1,189 @@
+use kb_layout_evaluator::{
+    constraints::{self, Constraint, Region},
+    delta::DeltaEvaluator,
+    Keyboard,
+};
+
+/// The best placement found by `solve`, which is optimal as the search is exhaustive
+pub struct Solution {
+    pub keyboard: Keyboard,
+    pub fitness: f64,
+    /// Partial and complete placements that were looked at
+    pub nodes: usize,
+    /// Complete placements that were evaluated
+    pub leaves: usize,
+}
+
+struct Search<'a> {
+    positions: Vec<(usize, usize)>,
+    constraints: &'a [Constraint],
+    evaluator: &'a DeltaEvaluator<'a>,
+    best: Option<(Keyboard, f64)>,
+    nodes: usize,
+    leaves: usize,
+}
+
+/// Finds the placement of `keys` on the positions of `region` with the lowest fitness, keeping
+/// every other key of `keyboard` where it is. Keys of `keyboard` within the region that aren't in
+/// `keys` take the positions `keys` leave, in order. Branch and bound: keys are placed one at a
+/// time, most typed first, and a partial placement is dropped as soon as the evaluator's lower
+/// bound shows that it can't beat the best complete placement found so far
+pub fn solve(
+    keyboard: &Keyboard,
+    keys: &[char],
+    region: Region,
+    constraints: &[Constraint],
+    evaluator: &DeltaEvaluator,
+) -> Option<Solution> {
+    let positions = region.positions().collect::<Vec<_>>();
+    assert_eq!(
+        keys.len(),
+        positions.len(),
+        "the region must have as many positions as there are keys to place"
+    );
+
+    let mut start = keyboard.clone();
+    let displaced_keys = positions
+        .iter()
+        .map(|&(x, y)| keyboard.keys[x][y])
+        .filter(|key| !keys.contains(key));
+    let vacated_positions = keys
+        .iter()
+        .map(|&key| keyboard.key_pos(key))
+        .filter(|&(x, y)| !region.contains(x, y));
+    for (key, (x, y)) in displaced_keys.zip(vacated_positions) {
+        start.keys[x][y] = key;
+    }
+
+    for &(x, y) in &positions {
+        start.keys[x][y] = '-';
+    }
+
+    let mut keys = keys.to_vec();
+    keys.sort_by_cached_key(|&key| std::cmp::Reverse(evaluator.key_presses(key)));
+    let mut search = Search {
+        positions,
+        constraints,
+        evaluator,
+        best: None,
+        nodes: 0,
+        leaves: 0,
+    };
+    search.branch(&mut start, &keys);
+    search.best.map(|(keyboard, fitness)| Solution {
+        keyboard,
+        fitness,
+        nodes: search.nodes,
+        leaves: search.leaves,
+    })
+}
+
+impl Search<'_> {
+    fn branch(&mut self, keyboard: &mut Keyboard, keys: &[char]) {
+        self.nodes += 1;
+        let best_fitness = self
+            .best
+            .as_ref()
+            .map_or(f64::INFINITY, |(_, fitness)| *fitness);
+        let Some((&key, remaining_keys)) = keys.split_first() else {
+            self.leaves += 1;
+            let fitness = self.evaluator.evaluate(keyboard).metrics.fitness();
+            if fitness < best_fitness {
+                self.best = Some((keyboard.clone(), fitness));
+            }
+
+            return;
+        };
+
+        if self.evaluator.lower_bound(keyboard) >= best_fitness {
+            return;
+        }
+
+        for i in 0..self.positions.len() {
+            let (x, y) = self.positions[i];
+            if keyboard.keys[x][y] != '-' || !constraints::can_place(key, x, y, self.constraints) {
+                continue;
+            }
+
+            keyboard.keys[x][y] = key;
+            self.branch(keyboard, remaining_keys);
+            keyboard.keys[x][y] = '-';
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kb_layout::Hand;
+    use kb_layout_evaluator::{parse_corpus, CHARS};
+
+    use super::*;
+
+    const CORPUS: &str = "\
+The quick brown fox jumps over the lazy dog.
+Pack my box with five dozen liquor jugs, said the sphinx of black quartz.
+How vexingly quick daft zebras jump while the wizard quickly jinxes the gnomes.
+";
+
+    // Every order of the keys, placed on the positions in turn
+    fn placements(keys: &[char]) -> Vec<Vec<char>> {
+        if keys.is_empty() {
+            return vec![Vec::new()];
+        }
+
+        (0..keys.len())
+            .flat_map(|i| {
+                let mut rest = keys.to_vec();
+                let key = rest.remove(i);
+                placements(&rest).into_iter().map(move |mut placement| {
+                    placement.insert(0, key);
+                    placement
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let (input, letter_freq) = parse_corpus(CORPUS, None);
+        let evaluator = DeltaEvaluator::new(&input, &letter_freq);
+        let mut keyboard = Keyboard::default();
+        for (i, &key) in CHARS.iter().enumerate() {
+            keyboard.keys[i / 3][i % 3] = key;
+        }
+
+        // The top and home row of the left pinky, ring and middle finger
+        let region = Region::hand(Hand::Left)
+            .without(Region::row(2))
+            .without(Region::finger(3));
+        let keys = region
+            .positions()
+            .map(|(x, y)| keyboard.keys[x][y])
+            .collect::<Vec<_>>();
+        let constraints = [Constraint::Hard {
+            keys: &['a'],
+            region: Region::row(1),
+        }];
+
+        let solution = solve(&keyboard, &keys, region, &constraints, &evaluator).unwrap();
+        let best = placements(&keys)
+            .into_iter()
+            .filter_map(|placement| {
+                let mut candidate = keyboard.clone();
+                for ((x, y), key) in region.positions().zip(placement) {
+                    candidate.keys[x][y] = key;
+                }
+
+                constraints::is_satisfied(&candidate, &constraints)
+                    .then(|| evaluator.evaluate(&candidate).metrics.fitness())
+            })
+            .fold(f64::INFINITY, f64::min);
+
+        assert_eq!(solution.fitness, best);
+        assert_eq!(
+            evaluator.evaluate(&solution.keyboard).metrics.fitness(),
+            solution.fitness
+        );
+        assert!(solution.leaves < placements(&keys).len());
+    }
+}
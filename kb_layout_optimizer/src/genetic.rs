@@ -0,0 +1,384 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rayon::{
+    prelude::{
+        IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+        IntoParallelRefMutIterator, ParallelIterator,
+    },
+    slice::ParallelSlice,
+};
+
+use kb_layout_evaluator::{
+    constraints::{self, Constraint, Region},
+    Keyboard, CHARS,
+};
+
+use crate::{
+    crossover, diversity,
+    run_log::{Generation, RunLog},
+    selection,
+    termination::Termination,
+    ADAPTIVE_MUTATION, CROSSOVER_OPERATOR, CROSSOVER_PROBABILITY, DIVERSITY_THRESHOLD, GENERATIONS,
+    ISLANDS, MAX_MUTATION_SCALE, MIGRANTS, MIGRATION_INTERVAL, MIGRATION_TOPOLOGY,
+    MUTATION_PROBABILITY, MUTATION_PROBABILITY_CONSTRAINED, MUTATION_SCALE_STEP, POPULATION_SIZE,
+    REPLACEMENT, SELECTION,
+};
+
+pub fn generate_individual(constraints: &[Constraint], rng: &mut StdRng) -> Keyboard {
+    for _ in 0..1000 {
+        let mut keyboard = Keyboard::default();
+        if constraints::fill(&mut keyboard, CHARS.to_vec(), constraints, rng) {
+            return keyboard;
+        }
+    }
+
+    panic!("could not generate a keyboard satisfying the hard constraints");
+}
+
+/// A population of `size` random individuals, except for `seed_share` of it made up of the seeds
+/// followed by mutated variants of them in turn. The seeds have to satisfy the hard constraints
+pub fn generate_population(
+    size: usize,
+    constraints: &[Constraint],
+    seeds: &[Keyboard],
+    seed_share: f64,
+    rng: &mut StdRng,
+) -> Vec<Keyboard> {
+    let mut keyboards = Vec::with_capacity(size);
+    if !seeds.is_empty() {
+        let seeded = ((size as f64 * seed_share).round() as usize)
+            .max(seeds.len())
+            .min(size);
+        for i in 0..seeded {
+            let mut keyboard = seeds[i % seeds.len()].clone();
+            if i >= seeds.len() {
+                mutate(&mut keyboard, 1.0, constraints, rng);
+            }
+
+            keyboards.push(keyboard);
+        }
+    }
+
+    while keyboards.len() < size {
+        keyboards.push(generate_individual(constraints, rng));
+    }
+
+    keyboards
+}
+
+/// Runs the genetic algorithm for GENERATIONS generations, or until a termination criterion is
+/// met, and returns the last population ranked
+/// by fitness, best first. `evaluate_population` is given the generation and the population, and
+/// returns the fitness of each individual where lower is better. Each generation is recorded
+/// in `log` when given
+pub fn run(
+    mut population: Vec<Keyboard>,
+    constraints: &[Constraint],
+    mut evaluate_population: impl FnMut(usize, &[Keyboard]) -> Vec<f64>,
+    mut log: Option<&mut RunLog>,
+    rng: &mut StdRng,
+) -> Vec<(Keyboard, f64)> {
+    let mut fitnesses = evaluate_population(0, &population);
+    let mut mutation_scale = 1.0;
+    let mut termination = Termination::new();
+    for g in 0..GENERATIONS {
+        let diversity = diversity::pairwise_distance(&population);
+        let entropy = diversity::mean_entropy(&population);
+        mutation_scale = adapt_mutation_scale(mutation_scale, diversity);
+        println!(
+            "[{}] best: {:.1}, avg: {:.1}, diversity: {:.3}, entropy: {:.3}",
+            g,
+            best_fitness(&fitnesses),
+            fitnesses.iter().sum::<f64>() / population.len() as f64,
+            diversity,
+            entropy
+        );
+        if let Some(log) = log.as_deref_mut() {
+            log.record(Generation {
+                generation: g,
+                population: &population,
+                fitnesses: &fitnesses,
+                diversity,
+                entropy,
+                mutation_scale,
+            })
+            .expect("could not write to the run log");
+        }
+
+        if let Some(reason) = termination.update(best_fitness(&fitnesses)) {
+            println!("stopping at generation {}: {}", g, reason);
+            break;
+        }
+
+        let offspring = breed(&population, &fitnesses, mutation_scale, constraints, rng);
+        let offspring_fitnesses = evaluate_population(g + 1, &offspring);
+        (population, fitnesses) = selection::replace(
+            REPLACEMENT,
+            population,
+            fitnesses,
+            offspring,
+            offspring_fitnesses,
+        );
+    }
+
+    rank(population, fitnesses)
+}
+
+#[derive(Clone, Copy, Debug)]
+// Not every topology is used by the default settings
+#[allow(dead_code)]
+pub enum Topology {
+    /// Each island sends its best individuals to the next island
+    Ring,
+    /// Each island sends its best individuals to every other island
+    FullyConnected,
+}
+
+/// Evolves ISLANDS populations side by side, every MIGRATION_INTERVAL generations the
+/// MIGRANTS best individuals of each island replace the worst individuals of the islands it is
+/// connected to. Every island starts out with its share of the seeds, see `generate_population`,
+/// and the islands are recorded together in `log` when given. Returns the best individual of
+/// each island
+pub fn run_islands(
+    constraints: &[Constraint],
+    seeds: &[Keyboard],
+    seed_share: f64,
+    evaluate: impl Fn(&Keyboard) -> f64 + Sync,
+    mut log: Option<&mut RunLog>,
+    rng: &mut StdRng,
+) -> Vec<(Keyboard, f64)> {
+    let island_size = POPULATION_SIZE / ISLANDS;
+    let mut islands = (0..ISLANDS)
+        .map(|_| generate_population(island_size, constraints, seeds, seed_share, rng))
+        .collect::<Vec<_>>();
+    // The islands evolve in parallel, each with its own RNG
+    let mut island_rngs = (0..ISLANDS)
+        .map(|_| StdRng::seed_from_u64(rng.gen()))
+        .collect::<Vec<_>>();
+    let evaluate_island =
+        |island: &[Keyboard]| -> Vec<f64> { island.par_iter().map(&evaluate).collect() };
+    let mut fitnesses = islands
+        .par_iter()
+        .map(|island| evaluate_island(island))
+        .collect::<Vec<Vec<f64>>>();
+    let mut mutation_scales = vec![1.0; ISLANDS];
+    let mut termination = Termination::new();
+    for g in 0..GENERATIONS {
+        println!(
+            "[{}] best: {}",
+            g,
+            fitnesses
+                .iter()
+                .map(|fitnesses| format!("{:.1}", best_fitness(fitnesses)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if let Some(log) = log.as_deref_mut() {
+            let population = islands.concat();
+            log.record(Generation {
+                generation: g,
+                population: &population,
+                fitnesses: &fitnesses.concat(),
+                diversity: diversity::pairwise_distance(&population),
+                entropy: diversity::mean_entropy(&population),
+                mutation_scale: mutation_scales.iter().sum::<f64>() / ISLANDS as f64,
+            })
+            .expect("could not write to the run log");
+        }
+
+        let overall_best_fitness = fitnesses
+            .iter()
+            .map(|fitnesses| best_fitness(fitnesses))
+            .fold(f64::INFINITY, f64::min);
+        if let Some(reason) = termination.update(overall_best_fitness) {
+            println!("stopping at generation {}: {}", g, reason);
+            break;
+        }
+
+        if g > 0 && g % MIGRATION_INTERVAL == 0 {
+            migrate(&mut islands, &mut fitnesses);
+        }
+
+        (islands, fitnesses) = islands
+            .into_par_iter()
+            .zip(fitnesses)
+            .zip(mutation_scales.par_iter_mut())
+            .zip(island_rngs.par_iter_mut())
+            .map(|(((island, fitnesses), mutation_scale), rng)| {
+                let diversity = diversity::pairwise_distance(&island);
+                *mutation_scale = adapt_mutation_scale(*mutation_scale, diversity);
+                let offspring = breed(&island, &fitnesses, *mutation_scale, constraints, rng);
+                let offspring_fitnesses = evaluate_island(&offspring);
+                selection::replace(
+                    REPLACEMENT,
+                    island,
+                    fitnesses,
+                    offspring,
+                    offspring_fitnesses,
+                )
+            })
+            .unzip();
+    }
+
+    islands
+        .into_iter()
+        .zip(fitnesses)
+        .map(|(island, fitnesses)| rank(island, fitnesses).swap_remove(0))
+        .collect()
+}
+
+fn migrate(islands: &mut [Vec<Keyboard>], fitnesses: &mut [Vec<f64>]) {
+    let migrants = islands
+        .iter()
+        .zip(fitnesses.iter())
+        .map(|(island, fitnesses)| {
+            rank(island.clone(), fitnesses.clone())
+                .into_iter()
+                .take(MIGRANTS)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    for (i, (island, fitnesses)) in islands.iter_mut().zip(fitnesses.iter_mut()).enumerate() {
+        let immigrants = migrants
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| match MIGRATION_TOPOLOGY {
+                Topology::Ring => (j + 1) % ISLANDS == i,
+                Topology::FullyConnected => j != i,
+            })
+            .flat_map(|(_, migrants)| migrants.iter().cloned());
+
+        let mut indices_worst_first = (0..island.len()).collect::<Vec<_>>();
+        indices_worst_first.sort_by(|&left, &right| {
+            fitnesses[right]
+                .partial_cmp(&fitnesses[left])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for (index, (individual, fitness)) in indices_worst_first.into_iter().zip(immigrants) {
+            island[index] = individual;
+            fitnesses[index] = fitness;
+        }
+    }
+}
+
+fn best_fitness(fitnesses: &[f64]) -> f64 {
+    fitnesses.iter().copied().fold(f64::INFINITY, f64::min)
+}
+
+// Sorts the population by fitness, best first
+fn rank(population: Vec<Keyboard>, fitnesses: Vec<f64>) -> Vec<(Keyboard, f64)> {
+    let mut ranked_population = population.into_iter().zip(fitnesses).collect::<Vec<_>>();
+    ranked_population.sort_by(|(_, left), (_, right)| {
+        left.partial_cmp(right).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    ranked_population
+}
+
+// Mutation gets more likely while the population has converged, so that it keeps exploring
+// instead of stagnating, and goes back to the configured rates once it has spread out again
+fn adapt_mutation_scale(mutation_scale: f64, diversity: f64) -> f64 {
+    if !ADAPTIVE_MUTATION {
+        1.0
+    } else if diversity < DIVERSITY_THRESHOLD {
+        (mutation_scale * MUTATION_SCALE_STEP).min(MAX_MUTATION_SCALE)
+    } else {
+        (mutation_scale / MUTATION_SCALE_STEP).max(1.0)
+    }
+}
+
+// Selects parents and crosses and mutates them into as many offspring as there are individuals,
+// the last parent of an odd number of them is only mutated. The pairs of parents are bred in
+// parallel, each with an RNG seeded from `rng` so that the offspring don't depend on the order
+// the threads run in
+fn breed(
+    population: &[Keyboard],
+    fitnesses: &[f64],
+    mutation_scale: f64,
+    constraints: &[Constraint],
+    rng: &mut StdRng,
+) -> Vec<Keyboard> {
+    let parent_indices = selection::select_parents(SELECTION, fitnesses, population.len(), rng);
+    let pair_seeds = (0..parent_indices.len().div_ceil(2))
+        .map(|_| rng.gen())
+        .collect::<Vec<u64>>();
+    parent_indices
+        .par_chunks(2)
+        .zip(pair_seeds)
+        .flat_map(|(parent_indices, seed)| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut offspring = match *parent_indices {
+                [first, second] => {
+                    let (first_new, second_new) = cross(
+                        &population[first],
+                        &population[second],
+                        constraints,
+                        &mut rng,
+                    );
+                    vec![first_new, second_new]
+                }
+                _ => vec![population[parent_indices[0]].clone()],
+            };
+            for individual in &mut offspring {
+                mutate(individual, mutation_scale, constraints, &mut rng);
+            }
+
+            offspring
+        })
+        .collect()
+}
+
+pub fn cross(
+    first_individual: &Keyboard,
+    second_individual: &Keyboard,
+    constraints: &[Constraint],
+    rng: &mut StdRng,
+) -> (Keyboard, Keyboard) {
+    if rng.gen::<f64>() > CROSSOVER_PROBABILITY {
+        return (first_individual.clone(), second_individual.clone());
+    }
+
+    crossover::cross(
+        first_individual,
+        second_individual,
+        CROSSOVER_OPERATOR,
+        constraints,
+        rng,
+    )
+}
+
+// Swaps keys with a random other key that they can trade places with without breaking any hard
+// constraint. Constrained keys have fewer positions to choose from and mutate more often, and
+// `mutation_scale` multiplies both probabilities
+pub fn mutate(
+    individual: &mut Keyboard,
+    mutation_scale: f64,
+    constraints: &[Constraint],
+    rng: &mut StdRng,
+) {
+    for x in 0..10 {
+        for y in 0..3 {
+            let region = constraints::allowed_region(individual.keys[x][y], constraints);
+            let mutation_probability = if region == Region::ALL {
+                MUTATION_PROBABILITY
+            } else {
+                MUTATION_PROBABILITY_CONSTRAINED
+            };
+            if rng.gen::<f64>() >= mutation_probability * mutation_scale {
+                continue;
+            }
+
+            let swap_positions = region
+                .positions()
+                .filter(|&position| {
+                    position != (x, y)
+                        && constraints::can_swap(individual, (x, y), position, constraints)
+                })
+                .collect::<Vec<_>>();
+            if let Some(&position) = swap_positions.choose(rng) {
+                individual.swap((x, y), position);
+            }
+        }
+    }
+
+    debug_assert!(constraints::is_satisfied(individual, constraints));
+}
@@ -0,0 +1,107 @@
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use kb_layout_evaluator::{
+    constraints::{self, Constraint},
+    delta::{DeltaEvaluator, Evaluation},
+    Keyboard,
+};
+
+use crate::HILL_CLIMBING_THREE_CYCLES;
+
+#[derive(Clone, Copy)]
+enum Move {
+    Swap((usize, usize), (usize, usize)),
+    // The key at the first position moves to the second, the second to the third and the third
+    // to the first
+    Cycle((usize, usize), (usize, usize), (usize, usize)),
+}
+
+impl Move {
+    fn is_allowed(self, keyboard: &Keyboard, constraints: &[Constraint]) -> bool {
+        match self {
+            Self::Swap(first, second) => {
+                constraints::can_swap(keyboard, first, second, constraints)
+            }
+            Self::Cycle(first, second, third) => {
+                let can_move = |(from_x, from_y): (usize, usize), (to_x, to_y): (usize, usize)| {
+                    constraints::can_place(keyboard.keys[from_x][from_y], to_x, to_y, constraints)
+                };
+                can_move(first, second) && can_move(second, third) && can_move(third, first)
+            }
+        }
+    }
+
+    fn apply(
+        self,
+        keyboard: &Keyboard,
+        evaluation: &Evaluation,
+        evaluator: &DeltaEvaluator,
+    ) -> (Keyboard, Evaluation) {
+        let mut keyboard = keyboard.clone();
+        let mut evaluation = evaluation.clone();
+        let swaps = match self {
+            Self::Swap(first, second) => vec![(first, second)],
+            Self::Cycle(first, second, third) => vec![(first, second), (first, third)],
+        };
+        for (first, second) in swaps {
+            evaluation = evaluator.swap(&keyboard, &evaluation, first, second);
+            keyboard.swap(first, second);
+        }
+
+        (keyboard, evaluation)
+    }
+}
+
+fn moves() -> Vec<Move> {
+    let positions = (0..30).map(|i| (i / 3, i % 3)).collect::<Vec<_>>();
+    let mut moves = Vec::new();
+    for i in 0..30 {
+        for j in i + 1..30 {
+            moves.push(Move::Swap(positions[i], positions[j]));
+            if !HILL_CLIMBING_THREE_CYCLES {
+                continue;
+            }
+
+            for k in j + 1..30 {
+                moves.push(Move::Cycle(positions[i], positions[j], positions[k]));
+                moves.push(Move::Cycle(positions[i], positions[k], positions[j]));
+            }
+        }
+    }
+
+    moves
+}
+
+/// Steepest descent: applies the move that improves the fitness the most until no swap (or
+/// three key cycle) improves it, and returns the polished layout along with its fitness
+pub fn polish(
+    mut keyboard: Keyboard,
+    constraints: &[Constraint],
+    evaluator: &DeltaEvaluator,
+) -> (Keyboard, f64) {
+    let moves = moves();
+    let mut evaluation = evaluator.evaluate(&keyboard);
+    loop {
+        let fitness = evaluation.metrics.fitness();
+        let best_move = moves
+            .par_iter()
+            .filter(|mv| mv.is_allowed(&keyboard, constraints))
+            .map(|mv| mv.apply(&keyboard, &evaluation, evaluator))
+            .min_by(|(_, left), (_, right)| {
+                left.metrics
+                    .fitness()
+                    .partial_cmp(&right.metrics.fitness())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        match best_move {
+            Some((new_keyboard, new_evaluation)) if new_evaluation.metrics.fitness() < fitness => {
+                keyboard = new_keyboard;
+                evaluation = new_evaluation;
+            }
+            _ => break,
+        }
+    }
+
+    let fitness = evaluation.metrics.fitness();
+    (keyboard, fitness)
+}
@@ -0,0 +1,234 @@
+use std::path::Path;
+
+use kb_layout::{Key, Layer, Layout};
+use serde_json::Value;
+
+use kb_layout_evaluator::{physical, Keyboard};
+
+/// Reads the 3x10 grid of a layout file. The format is told by the extension:
+/// - .json for the keymap.json of QMK Configurator, or for the JSON of a layout analyzer
+/// - .c for a QMK keymap.c
+/// - .keymap for ZMK
+/// - anything else for text with the rows of the layout
+///
+/// The keys of a keymap are matched to a keyboard with rows of the given lengths, or to the 3x10
+/// grid when none are given
+pub fn load(path: &Path, row_lengths: &[usize]) -> Result<Keyboard, String> {
+    let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let keyboard = if row_lengths.is_empty() {
+        physical::keyboard()
+    } else {
+        physical::grid_keyboard(row_lengths)
+    };
+    let layout = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => {
+            let json = serde_json::from_str::<Value>(&contents).map_err(|err| err.to_string())?;
+            if json.get("layers").is_none() {
+                return grid(&analyzer_json(&json)?);
+            }
+
+            kb_layout::qmk::parse_keymap_json(&keyboard, &contents)
+        }
+        Some("c") => kb_layout::qmk::parse_keymap_c(&keyboard, &contents),
+        Some("keymap") => kb_layout::zmk::parse_keymap(&keyboard, &contents),
+        _ => return grid(&text_rows(&contents)?),
+    }
+    .map_err(|err| err.to_string())?;
+    physical::grid(&keyboard, &layout)
+}
+
+// The JSON layouts of genkey and oxeylyzer list the keys row by row, either as lists of keys or
+// as strings, while cmini has an object with the row and column of every key
+fn analyzer_json(json: &Value) -> Result<Vec<Vec<char>>, String> {
+    let keys = json.get("keys").ok_or("the layout has no keys")?;
+    let key_char = |key: &str| {
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(format!("{} is not a single character", key)),
+        }
+    };
+
+    match keys {
+        Value::Array(rows) => rows
+            .iter()
+            .map(|row| match row {
+                Value::String(row) => Ok(row_keys(row)),
+                Value::Array(keys) => keys
+                    .iter()
+                    .map(|key| key_char(key.as_str().ok_or("a key is not a string")?))
+                    .collect(),
+                _ => Err("a row is neither a string nor a list of keys".to_string()),
+            })
+            .collect(),
+        Value::Object(keys) => {
+            let mut rows = vec![vec![' '; 10]; 3];
+            for (key, position) in keys {
+                let coordinate = |name: &str| {
+                    position[name]
+                        .as_u64()
+                        .ok_or_else(|| format!("the key {} has no {}", key, name))
+                };
+                let (row, col) = (coordinate("row")?, coordinate("col")?);
+                // Thumb keys and the keys beside the grid are left out
+                if row < 3 && col < 10 {
+                    rows[row as usize][col as usize] = key_char(key)?;
+                }
+            }
+
+            Ok(rows)
+        }
+        _ => Err("the keys are neither rows nor an object".to_string()),
+    }
+}
+
+// Layouts as they are shared in text: rows with the keys separated by spaces, like the .kb files
+// of oxeylyzer, or written together as a 3x10 block. genkey puts the name of the layout above
+// the rows and the fingers of the keys, as digits, below them. The rows are the last three of the
+// first lines in a row with ten or more keys that aren't all digits
+fn text_rows(text: &str) -> Result<Vec<Vec<char>>, String> {
+    let mut rows = Vec::new();
+    for line in text.lines() {
+        let keys = row_keys(line);
+        if keys.len() >= 10 && !keys.iter().all(char::is_ascii_digit) {
+            rows.push(keys);
+        } else if rows.len() >= 3 {
+            break;
+        } else {
+            rows.clear();
+        }
+    }
+
+    if rows.len() < 3 {
+        return Err("there are no three rows of ten keys".to_string());
+    }
+
+    Ok(rows.split_off(rows.len() - 3))
+}
+
+// The keys of a row with the keys separated by spaces or written together. Anything else, like
+// the name of the layout, has no keys
+fn row_keys(row: &str) -> Vec<char> {
+    let words = row.split_whitespace().collect::<Vec<_>>();
+    match &words[..] {
+        [word] => word.chars().collect(),
+        words if words.iter().all(|word| word.chars().count() == 1) => {
+            words.iter().flat_map(|word| word.chars()).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+// The 3x10 grid of rows of keys, the first ten keys of each row are on the grid
+fn grid(rows: &[Vec<char>]) -> Result<Keyboard, String> {
+    if rows.len() < 3 {
+        return Err(format!("the layout has {} rows, not 3", rows.len()));
+    }
+
+    let mut keys = Vec::with_capacity(30);
+    for (y, row) in rows.iter().take(3).enumerate() {
+        if row.len() < 10 {
+            return Err(format!("row {} has {} keys, not 10", y, row.len()));
+        }
+
+        keys.extend(row[..10].iter().map(|&c| Key::Char(c)));
+    }
+
+    let keyboard = physical::keyboard();
+    let layer = Layer::from_matrix_order(&keyboard, "base".to_string(), keys)
+        .map_err(|err| err.to_string())?;
+    physical::grid(
+        &keyboard,
+        &Layout {
+            layers: vec![layer],
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Colemak, the / key of the files is the ? of the grid
+    fn colemak() -> Keyboard {
+        let mut keyboard = Keyboard::default();
+        for (y, row) in ["qwfpgjluy;", "arstdhneio", "zxcvbkm,.?"]
+            .iter()
+            .enumerate()
+        {
+            for (x, key) in row.chars().enumerate() {
+                keyboard.keys[x][y] = key;
+            }
+        }
+
+        keyboard
+    }
+
+    fn text(text: &str) -> Result<Keyboard, String> {
+        grid(&text_rows(text)?)
+    }
+
+    fn json(json: &str) -> Result<Keyboard, String> {
+        grid(&analyzer_json(&serde_json::from_str(json).unwrap())?)
+    }
+
+    #[test]
+    fn text_block() {
+        let keyboard = text("qwfpgjluy;\narstdhneio\nzxcvbkm,./\n").unwrap();
+        assert_eq!(keyboard.keys, colemak().keys);
+    }
+
+    #[test]
+    fn genkey_text() {
+        let keyboard = text(
+            "colemak
+q w f p g j l u y ; [
+a r s t d h n e i o '
+z x c v b k m , . /
+0 1 2 3 3 6 6 7 8 9 9
+0 1 2 3 3 6 6 7 8 9 9
+0 1 2 3 3 6 6 7 8 9
+",
+        )
+        .unwrap();
+        assert_eq!(keyboard.keys, colemak().keys);
+    }
+
+    #[test]
+    fn oxeylyzer_kb() {
+        let keyboard = text(
+            "q w f p g  j l u y ;
+a r s t d  h n e i o
+z x c v b  k m , . /
+",
+        )
+        .unwrap();
+        assert_eq!(keyboard.keys, colemak().keys);
+    }
+
+    #[test]
+    fn analyzer_json_rows() {
+        let strings =
+            json(r#"{"name": "colemak", "keys": ["qwfpgjluy;", "arstdhneio", "zxcvbkm,./"]}"#)
+                .unwrap();
+        assert_eq!(strings.keys, colemak().keys);
+
+        let lists = json(
+            r#"{"keys": [
+                ["q", "w", "f", "p", "g", "j", "l", "u", "y", ";"],
+                ["a", "r", "s", "t", "d", "h", "n", "e", "i", "o"],
+                ["z", "x", "c", "v", "b", "k", "m", ",", ".", "/"]
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(lists.keys, colemak().keys);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(text("qwfpgjluy;\narstdhneio\n").is_err());
+        assert!(text("qwfpgjluy;\narstdhneio\nzxcvbkm,.q\n").is_err());
+        assert!(json(r#"{"keys": [["q", "wf"]]}"#).is_err());
+        assert!(json(r#"{"name": "colemak"}"#).is_err());
+    }
+}
@@ -1,83 +1,43 @@
-use std::collections::HashMap;
-
-use lazy_static::lazy_static;
-use rand::{seq::SliceRandom, thread_rng, Rng};
-use rayon::{
-    prelude::{
-        IntoParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
-    },
-    slice::ParallelSlice,
+mod annealing;
+mod compare;
+mod crossover;
+mod diversity;
+mod exact;
+mod genetic;
+mod hill_climbing;
+mod import;
+mod pareto;
+mod run_log;
+mod selection;
+mod similarity;
+mod termination;
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-#[derive(Clone, Debug)]
-struct Keyboard {
-    keys: [[char; 3]; 10],
-}
-
-impl Default for Keyboard {
-    fn default() -> Self {
-        Self {
-            keys: [['-'; 3]; 10],
-        }
-    }
-}
-
-impl Keyboard {
-    fn print(&self) {
-        for y in 0..3 {
-            for x in 0..10 {
-                print!("{} ", self.keys[x][y]);
-            }
-
-            println!();
-        }
-    }
-
-    fn print_freq(&self, letter_freq: &HashMap<char, f64>) {
-        let mut left_hand_usage = 0.0;
-        for x in 0..5 {
-            for y in 0..3 {
-                left_hand_usage += letter_freq.get(&self.keys[x][y]).unwrap();
-            }
-        }
-
-        println!("Hand usage: {} {}", left_hand_usage, 1.0 - left_hand_usage);
-        for y in 0..3 {
-            for x in 0..10 {
-                print!("{:.04} ", letter_freq.get(&self.keys[x][y]).unwrap());
-            }
-
-            println!();
-        }
-    }
-
-    fn key_pos(&self, key: char) -> (usize, usize) {
-        for x in 0..10 {
-            for y in 0..3 {
-                if self.keys[x][y] == key {
-                    return (x, y);
-                }
-            }
-        }
-
-        unreachable!("{}", key);
-    }
-
-    fn key_finger(&self, key: char) -> usize {
-        let (x, _) = self.key_pos(key);
-        match x {
-            4 => 3,
-            5 => 6,
-            n => n,
-        }
-    }
-}
+use annealing::CoolingSchedule;
+use clap::{Parser, Subcommand, ValueEnum};
+use compare::TableFormat;
+use crossover::CrossoverOperator;
+use genetic::Topology;
+use kb_layout::{board::Board, kanata::Remapper, svg::Coloring, Key};
+use kb_layout_evaluator::{
+    breakdown,
+    constraints::{self, Constraint, Region},
+    delta::DeltaEvaluator,
+    evaluate_individual, measure, parse_corpus, physical, Keyboard, CONSTRAINTS, HOME_ROW,
+    MOST_COMMON_CHARS,
+};
+use lazy_static::lazy_static;
+use rand::{rngs::StdRng, SeedableRng};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use run_log::{LogFormat, Run, RunLog};
+use selection::{Replacement, Selection};
+use similarity::MigrationArchive;
 
-const CHARS: [char; 30] = [
-    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
-    't', 'u', 'v', 'w', 'x', 'y', 'z', ',', '.', '?', ';',
-];
-const MOST_COMMON_CHARS: [char; 8] = ['e', 't', 'a', 'o', 'i', 'n', 's', 'r'];
 lazy_static! {
     static ref BIGRAMS: Vec<(String, i32)> = include_str!("../data/english_bigrams_1.txt")
         .lines()
@@ -168,444 +128,881 @@ const POPULATION_SIZE: usize = 200;
 const GENERATIONS: usize = 500;
 const TOURNAMENT_PROBABILTY: f64 = 0.8;
 const TOURNAMENT_SIZE: usize = 2;
+const SELECTION: Selection = Selection::Tournament;
+// Share of the population that truncation selection picks parents from
+const TRUNCATION_SHARE: f64 = 0.3;
+const REPLACEMENT: Replacement = Replacement::Generational;
 const MUTATION_PROBABILITY: f64 = 1.0 / 22.0;
-const MUTATION_PROBABILITY_COMMON: f64 = 1.0 / 8.0;
+const MUTATION_PROBABILITY_CONSTRAINED: f64 = 1.0 / 8.0;
+// Scale the mutation probabilities up by MUTATION_SCALE_STEP each generation the population
+// diversity (see diversity::pairwise_distance) stays below DIVERSITY_THRESHOLD, up to
+// MAX_MUTATION_SCALE times, and back down again once it recovers
+const ADAPTIVE_MUTATION: bool = false;
+const DIVERSITY_THRESHOLD: f64 = 0.2;
+const MUTATION_SCALE_STEP: f64 = 1.25;
+const MAX_MUTATION_SCALE: f64 = 8.0;
+// Stop before GENERATIONS when the best fitness hasn't improved in this many generations, when
+// the run has taken this long or when the best fitness is this low
+const STAGNATION_GENERATIONS: Option<usize> = None;
+const TIME_BUDGET: Option<Duration> = None;
+const TARGET_FITNESS: Option<f64> = None;
 const CROSSOVER_PROBABILITY: f64 = 0.8;
+const CROSSOVER_OPERATOR: CrossoverOperator = CrossoverOperator::SinglePoint;
+// Share of the initial population made up of seed layouts and mutated variants of them, when
+// seeds are given
+const SEED_SHARE: f64 = 0.1;
+// Island model, the population is split evenly between the islands
+const ISLANDS: usize = 4;
+const MIGRATION_INTERVAL: usize = 25;
+const MIGRANTS: usize = 2;
+const MIGRATION_TOPOLOGY: Topology = Topology::Ring;
+// How much the weighted distance to the reference layout counts against the fitness when
+// migrating, decays to zero over the generations
+const SIMILARITY_WEIGHT: f64 = 10.0;
+
+// Simulated annealing settings, the temperature is relative to the current fitness, i.e. at a
+// temperature of 0.01 a swap that makes the layout 1% worse is accepted with probability 1/e
+const ANNEALING_RESTARTS: usize = 8;
+const ANNEALING_ITERATIONS: usize = 100000;
+const ANNEALING_COOLING: CoolingSchedule = CoolingSchedule::Exponential {
+    start: 0.05,
+    end: 0.0001,
+};
 
-fn generate_individual() -> Keyboard {
-    let mut most_common_chars = MOST_COMMON_CHARS.to_vec();
-    most_common_chars.shuffle(&mut thread_rng());
-    let mut chars = CHARS.to_vec();
-    chars.retain(|key| !most_common_chars.contains(key));
-    chars.shuffle(&mut thread_rng());
-    let mut keyboard = Keyboard::default();
-
-    for i in 0..10 {
-        keyboard.keys[i][0] = chars.pop().unwrap();
-        keyboard.keys[i][2] = chars.pop().unwrap();
-        if i < 4 || i > 5 {
-            keyboard.keys[i][1] = most_common_chars.pop().unwrap();
-        } else {
-            keyboard.keys[i][1] = chars.pop().unwrap();
-        }
-    }
+// Whether hill climbing also tries moving three keys in a cycle, which finds more improvements.
+// There are 8120 cycles next to the 435 swaps, about 20 times as many moves, and each cycle is
+// evaluated as two swaps. Polishing COLEMAK takes about 16 s with them instead of 1 s on one core
+const HILL_CLIMBING_THREE_CYCLES: bool = true;
+// Exact solver, places these keys optimally on the positions of the region with the rest of the
+// layout fixed. The search grows with the factorial of the number of keys
+const EXACT_KEYS: &[char] = &MOST_COMMON_CHARS;
+const EXACT_REGION: Region = HOME_ROW;
+
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Write per generation statistics of the genetic algorithm to this file, for the optimize,
+    /// migrate and islands commands
+    #[arg(long, global = true)]
+    log: Option<PathBuf>,
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Jsonl)]
+    log_format: LogFormat,
+    /// Seed of the random number generator, to reproduce a run. A random seed is used, and
+    /// printed, when not given
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+    /// Seed the initial population of the optimize, pareto and islands commands with these
+    /// layouts and mutated variants of them
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    seed_layout: Vec<ReferenceLayout>,
+    /// Layout files to seed the initial population with, see evaluate --file for the formats.
+    /// Keymaps have to have the 30 keys of the grid
+    #[arg(long, global = true, num_args = 1..)]
+    seed_file: Vec<PathBuf>,
+    /// Share of the initial population made up of the seeds and their variants
+    #[arg(long, global = true, default_value_t = SEED_SHARE)]
+    seed_share: f64,
+}
 
-    keyboard
+#[derive(Subcommand)]
+enum Command {
+    /// Evaluate the reference layouts and optimize a layout with the genetic algorithm (default)
+    Optimize {
+        /// File to write the optimized layout to, as text with the rows of the layout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Find the best layout for each number of keys moved from a reference layout
+    Migrate {
+        #[arg(long, value_enum, default_value_t = ReferenceLayout::Qwerty)]
+        reference: ReferenceLayout,
+    },
+    /// Optimize the objectives separately with NSGA-II and print the Pareto front
+    Pareto,
+    /// Optimize a layout with simulated annealing
+    Anneal {
+        /// File to write the best layout to, as text with the rows of the layout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Optimize layouts with the genetic algorithm on separate islands with migration between them
+    Islands {
+        /// File to write the best layout of all islands to, as text with the rows of the layout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Find the optimal placement of EXACT_KEYS on EXACT_REGION, keeping the rest of a layout
+    Exact {
+        #[arg(long, value_enum, default_value_t = ReferenceLayout::Candidate1)]
+        reference: ReferenceLayout,
+    },
+    /// Break the fitness of a layout down into its components and the n-grams that cost the most
+    Evaluate {
+        #[arg(long, value_enum, default_value_t = ReferenceLayout::Qwerty)]
+        reference: ReferenceLayout,
+        /// Evaluate the layout of a file instead: a QMK keymap.json or keymap.c, a ZMK .keymap,
+        /// the JSON of genkey, oxeylyzer or cmini, or text with the rows of the layout
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Number of keys on each row of the keyboard of the keymap file, like 12,12,12,6. The
+        /// middle ten keys of the first three rows are evaluated
+        #[arg(long, value_delimiter = ',')]
+        key_rows: Vec<usize>,
+        /// Print the breakdown as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Draw a layout as an SVG image
+    Render {
+        #[arg(long, value_enum, default_value_t = ReferenceLayout::Qwerty)]
+        reference: ReferenceLayout,
+        /// Draw the layout of a file instead, see evaluate --file for the formats
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Number of keys on each row of the keyboard of the keymap file, see evaluate --key-rows
+        #[arg(long, value_delimiter = ',')]
+        key_rows: Vec<usize>,
+        /// Draw the layout on an ANSI or ISO board, with the keys around the 3x10 grid as on the
+        /// US layout, instead of on the grid alone
+        #[arg(long, value_enum)]
+        board: Option<PhysicalBoard>,
+        #[arg(long, value_enum, default_value_t = KeyColoring::Frequency)]
+        coloring: KeyColoring,
+        /// File to write the image to, printed when not given
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Write a layout in the keymap format of a keyboard firmware or operating system
+    Export {
+        #[arg(long, value_enum, default_value_t = ReferenceLayout::Candidate1)]
+        reference: ReferenceLayout,
+        /// Export the layout of a file instead, see evaluate --file for the formats. The layout
+        /// is named after the file
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Number of keys on each row of the keyboard of the keymap file, see evaluate --key-rows
+        #[arg(long, value_delimiter = ',')]
+        key_rows: Vec<usize>,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Qmk)]
+        format: ExportFormat,
+        /// Name of the QMK macro the keys are passed to, it depends on the keyboard
+        #[arg(long, default_value = "LAYOUT")]
+        layout_macro: String,
+        /// Board the formats for operating systems map the layout onto
+        #[arg(long, value_enum, default_value_t = PhysicalBoard::Ansi)]
+        board: PhysicalBoard,
+        /// File to write the keymap to, printed when not given. The XKB rules snippet is written
+        /// next to it with an .xml extension
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Evaluate layouts on one or more corpora and compare every metric side by side
+    Compare {
+        #[arg(long, value_enum, num_args = 1.., default_values_t = ReferenceLayout::value_variants().to_vec())]
+        layouts: Vec<ReferenceLayout>,
+        /// Layout the differences in percent are relative to
+        #[arg(long, value_enum, default_value_t = ReferenceLayout::Qwerty)]
+        baseline: ReferenceLayout,
+        /// Layout files to compare along with the built in layouts, named after the file. See
+        /// evaluate --file for the formats
+        #[arg(long, num_args = 1..)]
+        files: Vec<PathBuf>,
+        /// Number of keys on each row of the keyboard of the keymap files, see evaluate
+        #[arg(long, value_delimiter = ',')]
+        key_rows: Vec<usize>,
+        /// Corpus files with one sentence per line, optionally preceded by a tab separated id.
+        /// The built in corpus is used when none are given
+        #[arg(long, num_args = 1..)]
+        corpus: Vec<PathBuf>,
+        #[arg(long, value_enum, default_value_t = TableFormat::Text)]
+        format: TableFormat,
+    },
 }
 
-fn generate_population() -> Vec<Keyboard> {
-    let mut keyboards = Vec::with_capacity(POPULATION_SIZE);
-    for _ in 0..POPULATION_SIZE {
-        keyboards.push(generate_individual());
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Optimize { .. } => "optimize",
+            Self::Migrate { .. } => "migrate",
+            Self::Pareto => "pareto",
+            Self::Anneal { .. } => "anneal",
+            Self::Islands { .. } => "islands",
+            Self::Exact { .. } => "exact",
+            Self::Evaluate { .. } => "evaluate",
+            Self::Render { .. } => "render",
+            Self::Export { .. } => "export",
+            Self::Compare { .. } => "compare",
+        }
     }
 
-    keyboards
+    // Commands that evaluate given layouts type every sentence of the corpus, so that they don't
+    // depend on the seed. The others draw from the random number generator
+    fn is_random(&self) -> bool {
+        !matches!(
+            self,
+            Self::Evaluate { .. }
+                | Self::Render { .. }
+                | Self::Export { .. }
+                | Self::Compare { .. }
+        )
+    }
 }
 
-// Lika använding av händer och fingrar för jämt slitage, eller proportioneligt på något vis iallafall.
-// Ta hänsyn till musanvänding, typ dominant hand
-// Fundera på rimlig input att beräkna kostnaden på, typ vilken text/kod. Sampla github?
-// Finger rolling, att använda fingrar brevid för nästa key, behöver kanske mer än en window size på 2 (bigram)
-// Vikt/kostnad för att flytta olika fingrar i olika riktningar
-// flera lager, men det måste kosta mer att använda tummen pga koordination och stoppar flowet
-
-// params (with weights):
-//  * dont use the same finger for two keys in row
-const SAME_FINGER_PENALTY: f64 = 2.0;
-
-//  * alternating hands
-const SAME_HAND_PENALTY: f64 = 2.0;
-
-//  * disfavour bottom row
-const BOTTOM_ROW_PENALTY: f64 = 5.0;
-
-//  * rolls, outer to inner finger mostly, more awkward inner to outer direction
+#[derive(Clone, Copy, ValueEnum)]
+enum KeyColoring {
+    /// The finger each key is typed with
+    Finger,
+    /// How often each key is typed
+    Frequency,
+    /// What typing each key adds to the fitness, before the multipliers
+    Cost,
+}
 
-//  * finger strength, i.e. pinky is weaker
-//  * hand/finger usage symmetry symmetry
-const FINGER_TARGET_USAGE: [f64; 10] = [0.1, 0.135, 0.135, 0.13, 0.0, 0.0, 0.13, 0.135, 0.135, 0.1];
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// keymap.c of a QMK keyboard
+    Qmk,
+    /// .keymap of a ZMK keyboard
+    Zmk,
+    /// XKB symbols file for Linux, along with the snippet for rules/evdev.xml
+    Xkb,
+    /// Microsoft Keyboard Layout Creator source file for Windows
+    Klc,
+    /// .keylayout file for macOS
+    Keylayout,
+    /// Configuration of the kanata remapper
+    Kanata,
+    /// Configuration of the KMonad remapper
+    Kmonad,
+}
 
-//  * idle time of fingers
-//  * physical restrictions of fingers in a hand
-//			e.g. one first on top row and the adjacent finger on bottom row on consecutive keys is bad
-fn evaluate_individual(
-    individual: &Keyboard,
-    input: &str,
-    letter_freq: &HashMap<char, f64>,
-) -> f64 {
-    let mut prev_finger_index = 0;
-    let mut fitness = 0.0;
-    let calc_distance = |prev_x: isize, prev_y: isize, x: isize, y: isize| {
-        let top = prev_y == 0 || y == 0;
-        let bottom = prev_y == 2 || y == 2;
-        let x_distance = (prev_x - x).abs() as f64;
-        let y_distance = match (top, bottom) {
-            (true, true) => 1.0 + BOTTOM_ROW_PENALTY,
-            (true, false) => 1.0,
-            (false, true) => BOTTOM_ROW_PENALTY,
-            (false, false) => 0.0,
-        };
-        (x_distance.powi(2) + y_distance.powi(2)).sqrt()
-    };
+#[derive(Clone, Copy, ValueEnum)]
+enum PhysicalBoard {
+    Ansi,
+    Iso,
+}
 
-    let mut finger_positions = [
-        (0, 1),
-        (1, 1),
-        (2, 1),
-        (3, 1),
-        (4, 1),
-        (5, 1),
-        (6, 1),
-        (7, 1),
-        (8, 1),
-        (9, 1),
-    ];
-    let mut finger_usage = [0; 10];
-    let mut same_finger_count = 0;
-    let mut same_hand_no_roll_count = 0;
-    let mut prev_key = '-';
-    for key in input.chars() {
-        let finger_idx = individual.key_finger(key);
-        let (x, y) = individual.key_pos(key);
-        let x = x as isize;
-        let y = y as isize;
-
-        let (start_x, start_y) = finger_positions[finger_idx];
-
-        fitness += calc_distance(start_x, start_y, x, y);
-        if finger_idx == prev_finger_index && prev_key != key {
-            same_finger_count += 1;
+impl PhysicalBoard {
+    fn board(self) -> Board {
+        match self {
+            Self::Ansi => Board::Ansi,
+            Self::Iso => Board::Iso,
         }
+    }
+}
 
-        let same_hand = match (finger_idx, prev_finger_index) {
-            (0..=4, 0..=4) => true,
-            (5..=9, 5..=9) => true,
-            _ => false,
-        };
-
-        let normalize_finger_idx = |finger_idx: usize| {
-            if finger_idx > 4 {
-                9 - finger_idx
-            } else {
-                finger_idx
-            }
-        };
-        let normalized_finger_idx = normalize_finger_idx(finger_idx);
-        let prev_normalized_finger_idx = normalize_finger_idx(prev_finger_index);
-        if same_hand && normalized_finger_idx < prev_normalized_finger_idx {
-            same_hand_no_roll_count += 1;
-        }
+#[derive(Clone, Copy, ValueEnum)]
+enum ReferenceLayout {
+    Qwerty,
+    Colemak,
+    Dvorak,
+    Miryoku,
+    Candidate1,
+}
 
-        finger_positions[finger_idx] = (x, y);
-        finger_usage[finger_idx] += 1;
-        prev_finger_index = finger_idx;
-        prev_key = key;
+impl ReferenceLayout {
+    fn name(self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
     }
 
-    fitness += same_finger_count as f64 * SAME_FINGER_PENALTY;
-    fitness += same_hand_no_roll_count as f64 * SAME_HAND_PENALTY;
-
-    let total_usage = finger_usage.iter().sum::<i32>() as f64;
-    fitness *= 1.0
-        + finger_usage
-            .into_iter()
-            .enumerate()
-            .map(|(finger_idx, usage)| {
-                (1.0 + (FINGER_TARGET_USAGE[finger_idx] - usage as f64 / total_usage).abs()).powi(3)
-                    - 1.0
-            })
-            .sum::<f64>();
-
-    let mut left_hand_usage = 0.0;
-    for x in 0..5 {
-        for y in 0..3 {
-            left_hand_usage += letter_freq.get(&individual.keys[x][y]).unwrap();
+    fn keyboard(self) -> Keyboard {
+        match self {
+            Self::Qwerty => QWERTY,
+            Self::Colemak => COLEMAK,
+            Self::Dvorak => DVORAK,
+            Self::Miryoku => MIRYOKU,
+            Self::Candidate1 => CANDIDATE_1,
         }
     }
+}
 
-    fitness *= 1.0 + (0.5 - left_hand_usage).abs().powi(3);
-
-    fitness
+// The built in corpus, or a random sample of it when given a random number generator, see
+// `parse_corpus`
+fn load_input(sample: Option<&mut StdRng>) -> (String, HashMap<char, f64>) {
+    parse_corpus(
+        include_str!("../data/eng-uk_web_2202_300K/eng-uk_web_2002_300K-sentences.txt"),
+        sample,
+    )
 }
 
-fn tournament_selection(fitnesses: &[f64]) -> usize {
-    let mut rng = rand::thread_rng();
-    let mut indices = Vec::with_capacity(TOURNAMENT_SIZE);
-    for _ in 0..TOURNAMENT_SIZE {
-        indices.push(rng.gen::<usize>() % fitnesses.len());
+fn optimize(
+    input: &str,
+    letter_freq: &HashMap<char, f64>,
+    seeds: &[Keyboard],
+    seed_share: f64,
+    log: Option<&mut RunLog>,
+    output: Option<&Path>,
+    rng: &mut StdRng,
+) {
+    println!(
+        "qwerty: {}",
+        evaluate_individual(&QWERTY, input, letter_freq)
+    );
+    QWERTY.print_freq(letter_freq);
+    println!(
+        "colemak: {}",
+        evaluate_individual(&COLEMAK, input, letter_freq)
+    );
+    COLEMAK.print_freq(letter_freq);
+    println!(
+        "dvorak: {}",
+        evaluate_individual(&DVORAK, input, letter_freq)
+    );
+    DVORAK.print_freq(letter_freq);
+    println!(
+        "miryoku: {}",
+        evaluate_individual(&MIRYOKU, input, letter_freq)
+    );
+    MIRYOKU.print_freq(letter_freq);
+    println!(
+        "candidate 1: {}",
+        evaluate_individual(&CANDIDATE_1, input, letter_freq)
+    );
+    CANDIDATE_1.print_freq(letter_freq);
+
+    let population =
+        genetic::generate_population(POPULATION_SIZE, CONSTRAINTS, seeds, seed_share, rng);
+    let mut ranked_population = genetic::run(
+        population,
+        CONSTRAINTS,
+        |_, population| {
+            population
+                .par_iter()
+                .map(|individual| evaluate_individual(individual, input, letter_freq))
+                .collect()
+        },
+        log,
+        rng,
+    );
+    let (keyboard, fitness) = ranked_population.swap_remove(0);
+    let evaluator = DeltaEvaluator::new(input, letter_freq);
+    let (polished_keyboard, polished_fitness) =
+        hill_climbing::polish(keyboard, CONSTRAINTS, &evaluator);
+    println!(
+        "hill climbing: {:.1} -> {:.1} ({:.2}% better)",
+        fitness,
+        polished_fitness,
+        100.0 * (fitness - polished_fitness) / fitness
+    );
+    polished_keyboard.print();
+    polished_keyboard.print_freq(letter_freq);
+    breakdown::breakdown(&polished_keyboard, input, letter_freq).print();
+    if let Some(path) = output {
+        polished_keyboard.save(path);
     }
+}
 
-    while indices.len() > 1 {
-        let index = indices.pop().unwrap();
-        if rng.gen::<f64>() < TOURNAMENT_PROBABILTY {
-            // lower is better
-            if fitnesses[index] < fitnesses[indices[indices.len() - 1]] {
-                return index;
-            }
-        }
+fn migrate(
+    reference: &Keyboard,
+    input: &str,
+    letter_freq: &HashMap<char, f64>,
+    log: Option<&mut RunLog>,
+    rng: &mut StdRng,
+) {
+    // Reference layouts rarely satisfy the hard constraints, which would keep the population from
+    // ever getting close to them
+    let constraints = CONSTRAINTS
+        .iter()
+        .filter(|constraint| matches!(constraint, Constraint::Soft { .. }))
+        .copied()
+        .collect::<Vec<_>>();
+    let mut population = vec![reference.clone(); POPULATION_SIZE];
+    for individual in population.iter_mut().skip(1) {
+        genetic::mutate(individual, 1.0, &constraints, rng);
     }
 
-    indices.pop().unwrap()
+    let mut archive = MigrationArchive::new();
+    genetic::run(
+        population,
+        &constraints,
+        |g, population| {
+            // Start out close to the reference and let the population drift away from it, so that
+            // every budget of moved keys gets explored
+            let similarity_weight = SIMILARITY_WEIGHT * (1.0 - g as f64 / GENERATIONS as f64);
+            let evaluations = population
+                .par_iter()
+                .map(|individual| {
+                    (
+                        evaluate_individual(individual, input, letter_freq),
+                        similarity::moved_keys(individual, reference),
+                        similarity::weighted_distance(individual, reference, letter_freq),
+                    )
+                })
+                .collect::<Vec<_>>();
+            population
+                .iter()
+                .zip(evaluations)
+                .map(|(individual, (fitness, moved_keys, distance))| {
+                    archive.insert(individual, moved_keys, fitness);
+                    fitness * (1.0 + similarity_weight * distance)
+                })
+                .collect()
+        },
+        log,
+        rng,
+    );
+
+    for (moved_keys, keyboard, fitness) in archive.budgets() {
+        println!(
+            "{} moved keys: {:.1} (weighted distance: {:.3})",
+            moved_keys,
+            fitness,
+            similarity::weighted_distance(keyboard, reference, letter_freq)
+        );
+        keyboard.print();
+    }
 }
 
-fn cross(first_individual: &Keyboard, second_individual: &Keyboard) -> (Keyboard, Keyboard) {
-    if rand::random::<f64>() > CROSSOVER_PROBABILITY {
-        return (first_individual.clone(), second_individual.clone());
+fn pareto_front(
+    input: &str,
+    letter_freq: &HashMap<char, f64>,
+    seeds: &[Keyboard],
+    seed_share: f64,
+    rng: &mut StdRng,
+) {
+    let population =
+        genetic::generate_population(POPULATION_SIZE, CONSTRAINTS, seeds, seed_share, rng);
+    let front = pareto::run(
+        population,
+        CONSTRAINTS,
+        |individual| measure(individual, input, letter_freq),
+        rng,
+    );
+    for solution in front {
+        println!(
+            "{}",
+            pareto::OBJECTIVES
+                .iter()
+                .zip(solution.objectives)
+                .map(|(objective, value)| format!("{}: {:.4}", objective, value))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        solution.keyboard.print();
     }
+}
 
-    let crossover_point = rand::random::<usize>() % 30;
-    let mut first_new_individual = Keyboard::default();
-    let mut second_new_individual = Keyboard::default();
-    for i in 0..30 {
-        if i < crossover_point {
-            first_new_individual.keys[i / 3][i % 3] = first_individual.keys[i / 3][i % 3];
-        } else {
-            second_new_individual.keys[i / 3][i % 3] = second_individual.keys[i / 3][i % 3];
-        }
+fn anneal(input: &str, letter_freq: &HashMap<char, f64>, output: Option<&Path>, rng: &mut StdRng) {
+    let evaluator = DeltaEvaluator::new(input, letter_freq);
+    let (keyboard, fitness) = annealing::run(CONSTRAINTS, &evaluator, rng);
+    println!("best: {:.1}", fitness);
+    keyboard.print();
+    keyboard.print_freq(letter_freq);
+    if let Some(path) = output {
+        keyboard.save(path);
     }
+}
 
-    // Fill in missing in first
-    let mut first_missing_common_chars = MOST_COMMON_CHARS.to_vec();
-    let mut first_missing_chars = CHARS.to_vec();
-    first_missing_chars.retain(|key| !MOST_COMMON_CHARS.contains(key));
-    for i in 0..crossover_point {
-        let current_key = first_new_individual.keys[i / 3][i % 3];
-        first_missing_chars.retain(|&key| key != current_key);
-        first_missing_common_chars.retain(|&key| key != current_key);
+fn islands(
+    input: &str,
+    letter_freq: &HashMap<char, f64>,
+    seeds: &[Keyboard],
+    seed_share: f64,
+    log: Option<&mut RunLog>,
+    output: Option<&Path>,
+    rng: &mut StdRng,
+) {
+    let best_individuals = genetic::run_islands(
+        CONSTRAINTS,
+        seeds,
+        seed_share,
+        |individual| evaluate_individual(individual, input, letter_freq),
+        log,
+        rng,
+    );
+    for (i, (keyboard, fitness)) in best_individuals.iter().enumerate() {
+        println!("island {}: {:.1}", i, fitness);
+        keyboard.print();
     }
 
-    for i in crossover_point..30 {
-        let x = i / 3;
-        let y = i % 3;
-        if first_missing_chars.contains(&second_individual.keys[x][y]) {
-            first_new_individual.keys[x][y] = second_individual.keys[x][y];
-            first_missing_chars.retain(|&key| key != second_individual.keys[x][y]);
-        } else if first_missing_common_chars.contains(&second_individual.keys[x][y]) {
-            first_new_individual.keys[x][y] = second_individual.keys[x][y];
-            first_missing_common_chars.retain(|&key| key != second_individual.keys[x][y]);
-        }
+    if let Some(path) = output {
+        let (keyboard, _) = best_individuals
+            .iter()
+            .min_by(|(_, left), (_, right)| {
+                left.partial_cmp(right).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        keyboard.save(path);
     }
+}
 
-    first_missing_common_chars.shuffle(&mut thread_rng());
-    first_missing_chars.shuffle(&mut thread_rng());
+fn exact(reference: &Keyboard, input: &str, letter_freq: &HashMap<char, f64>) {
+    let evaluator = DeltaEvaluator::new(input, letter_freq);
+    let reference_fitness = evaluator.evaluate(reference).metrics.fitness();
+    let Some(solution) = exact::solve(reference, EXACT_KEYS, EXACT_REGION, CONSTRAINTS, &evaluator)
+    else {
+        println!("no placement satisfies the hard constraints");
+        return;
+    };
 
-    for i in crossover_point..30 {
-        let x = i / 3;
-        let y = i % 3;
-        if first_new_individual.keys[x][y] == '-' {
-            if y == 1 && (x < 4 || x > 5) {
-                first_new_individual.keys[x][y] = first_missing_common_chars.pop().unwrap();
-            } else {
-                first_new_individual.keys[x][y] = first_missing_chars.pop().unwrap();
-            }
-        }
-    }
+    let placements = (1..=EXACT_KEYS.len()).product::<usize>();
+    println!(
+        "searched {} nodes, evaluated {} of {} placements",
+        solution.nodes, solution.leaves, placements
+    );
+    println!(
+        "reference: {:.1}, optimal: {:.1} ({:.2}% better)",
+        reference_fitness,
+        solution.fitness,
+        100.0 * (reference_fitness - solution.fitness) / reference_fitness
+    );
+    solution.keyboard.print();
+    solution.keyboard.print_freq(letter_freq);
+}
 
-    // Fill in missing in second
-    let mut second_missing_common_chars = MOST_COMMON_CHARS.to_vec();
-    let mut second_missing_chars = CHARS.to_vec();
-    second_missing_chars.retain(|key| !MOST_COMMON_CHARS.contains(key));
-    for i in crossover_point..30 {
-        let current_key = second_new_individual.keys[i / 3][i % 3];
-        second_missing_chars.retain(|&key| key != current_key);
-        second_missing_common_chars.retain(|&key| key != current_key);
+fn evaluate(keyboard: &Keyboard, input: &str, letter_freq: &HashMap<char, f64>, json: bool) {
+    let breakdown = breakdown::breakdown(keyboard, input, letter_freq);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&breakdown).unwrap());
+    } else {
+        keyboard.print();
+        breakdown.print();
     }
+}
 
-    for i in 0..crossover_point {
-        let x = i / 3;
-        let y = i % 3;
-        if second_missing_chars.contains(&first_individual.keys[x][y]) {
-            second_new_individual.keys[x][y] = first_individual.keys[x][y];
-            second_missing_chars.retain(|&key| key != first_individual.keys[x][y]);
-        } else if second_missing_common_chars.contains(&first_individual.keys[x][y]) {
-            second_new_individual.keys[x][y] = first_individual.keys[x][y];
-            second_missing_common_chars.retain(|&key| key != first_individual.keys[x][y]);
+fn render(
+    keyboard: &Keyboard,
+    board: Option<PhysicalBoard>,
+    coloring: KeyColoring,
+    output: Option<&Path>,
+    input: &str,
+    letter_freq: &HashMap<char, f64>,
+) {
+    let (physical_keyboard, layout) = match board {
+        Some(board) => (
+            board.board().keyboard(),
+            physical::board_layout(keyboard, board.board()),
+        ),
+        None => (physical::keyboard(), physical::layout(keyboard)),
+    };
+    // The keys of the grid on the buttons, the grid's ? is on the / key. The keys around the
+    // grid have no value
+    let keys = layout.layers[0]
+        .keys
+        .iter()
+        .map(|key| match key {
+            Key::Char('/') => Some('?'),
+            Key::Char(c) => Some(*c).filter(|&c| keyboard.contains(c)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let values = match coloring {
+        KeyColoring::Finger => Vec::new(),
+        KeyColoring::Frequency => keys
+            .iter()
+            .map(|key| key.map(|key| letter_freq[&key]))
+            .collect(),
+        KeyColoring::Cost => {
+            let key_costs = breakdown::breakdown(keyboard, input, letter_freq).key_costs;
+            keys.iter()
+                .map(|key| key.map(|key| key_costs.get(&key).copied().unwrap_or(0.0)))
+                .collect()
         }
-    }
-
-    second_missing_common_chars.shuffle(&mut thread_rng());
-    second_missing_chars.shuffle(&mut thread_rng());
+    };
+    let coloring = match coloring {
+        KeyColoring::Finger => Coloring::Finger,
+        KeyColoring::Frequency => Coloring::Frequency(&values),
+        KeyColoring::Cost => Coloring::Cost(&values),
+    };
 
-    for i in 0..crossover_point {
-        let x = i / 3;
-        let y = i % 3;
-        if second_new_individual.keys[x][y] == '-' {
-            if y == 1 && (x < 4 || x > 5) {
-                second_new_individual.keys[x][y] = second_missing_common_chars.pop().unwrap();
-            } else {
-                second_new_individual.keys[x][y] = second_missing_chars.pop().unwrap();
-            }
-        }
+    let svg = kb_layout::svg::render(&physical_keyboard, &layout.layers[0], &coloring);
+    match output {
+        Some(path) => std::fs::write(path, svg)
+            .unwrap_or_else(|err| panic!("could not write {}: {}", path.display(), err)),
+        None => print!("{}", svg),
     }
-
-    (first_new_individual, second_new_individual)
 }
 
-// rosens tagning: gå igenom alla keys och kolla om det ska mutera, lägg till deras keys i en pool, sätt alla nya keys som inte
-// muterar till det de var innan, och slumpa ut från poolen till de som ska mutera
-// dinos tagning: slumpa ordningen av index, gå igenom den i ordning, om en key finns i poolen (av alla keys) och inte ska mutera,
-// tar den den, annars tar den en slumpad
-fn mutate(individual: &mut Keyboard) {
-    let mut rng = rand::thread_rng();
-    let mut keys = Vec::new();
-    let mut key_positions = Vec::new();
-    let mut common_keys = Vec::new();
-    let mut common_key_positions = Vec::new();
-    for x in 0..10 {
-        for y in 0..3 {
-            if y == 1 && (x < 4 || x > 5) {
-                if rng.gen::<f64>() < MUTATION_PROBABILITY_COMMON {
-                    common_keys.push(individual.keys[x][y]);
-                    common_key_positions.push((x, y));
-                }
-            } else {
-                if rng.gen::<f64>() < MUTATION_PROBABILITY {
-                    keys.push(individual.keys[x][y]);
-                    key_positions.push((x, y));
-                }
-            }
+fn export(
+    name: &str,
+    keyboard: &Keyboard,
+    format: ExportFormat,
+    layout_macro: &str,
+    board: PhysicalBoard,
+    output: Option<&Path>,
+) {
+    let board = board.board();
+    let description = format!("English ({})", name);
+    let mut rules = None;
+    let keymap = match format {
+        ExportFormat::Qmk => kb_layout::qmk::keymap_c(
+            &physical::keyboard(),
+            &physical::layout(keyboard),
+            layout_macro,
+        ),
+        ExportFormat::Zmk => {
+            kb_layout::zmk::keymap(&physical::keyboard(), &physical::layout(keyboard))
+        }
+        ExportFormat::Xkb => {
+            rules = Some(kb_layout::xkb::rules_layout(name, &description));
+            kb_layout::xkb::symbols(
+                &board.keyboard(),
+                &physical::board_layout(keyboard, board),
+                &description,
+            )
         }
+        ExportFormat::Klc => kb_layout::klc::source(
+            &board.keyboard(),
+            &physical::board_layout(keyboard, board),
+            name,
+            &description,
+        ),
+        ExportFormat::Keylayout => kb_layout::keylayout::keylayout(
+            &board.keyboard(),
+            &physical::board_layout(keyboard, board),
+            &description,
+        ),
+        ExportFormat::Kanata => kb_layout::kanata::config(
+            &board.keyboard(),
+            &physical::board_layout(keyboard, board),
+            Remapper::Kanata,
+        ),
+        ExportFormat::Kmonad => kb_layout::kanata::config(
+            &board.keyboard(),
+            &physical::board_layout(keyboard, board),
+            Remapper::Kmonad,
+        ),
     }
+    .unwrap_or_else(|err| panic!("could not export the layout: {}", err));
 
-    keys.shuffle(&mut rng);
-    common_keys.shuffle(&mut rng);
-    for x in 0..10 {
-        for y in 0..3 {
-            if key_positions.contains(&(x, y)) {
-                individual.keys[x][y] = keys.pop().unwrap();
-            } else if common_key_positions.contains(&(x, y)) {
-                individual.keys[x][y] = common_keys.pop().unwrap();
+    let write = |path: &Path, contents: &[u8]| {
+        std::fs::write(path, contents)
+            .unwrap_or_else(|err| panic!("could not write {}: {}", path.display(), err))
+    };
+    match output {
+        Some(path) => {
+            let contents = match format {
+                ExportFormat::Klc => kb_layout::klc::encode(&keymap),
+                _ => keymap.into_bytes(),
+            };
+            write(path, &contents);
+            if let Some(rules) = rules {
+                write(&path.with_extension("xml"), rules.as_bytes());
+            }
+        }
+        None => {
+            print!("{}", keymap);
+            if let Some(rules) = rules {
+                println!();
+                print!("{}", rules);
             }
         }
     }
 }
 
-fn main() {
-    let mut raw_input =
-        include_str!("../data/eng-uk_web_2202_300K/eng-uk_web_2002_300K-sentences.txt")
-            .lines()
-            .collect::<Vec<_>>();
-    raw_input.shuffle(&mut rand::thread_rng());
-    let input = raw_input
+fn compare(
+    mut layouts: Vec<ReferenceLayout>,
+    baseline: ReferenceLayout,
+    files: &[PathBuf],
+    key_rows: &[usize],
+    corpus_paths: &[PathBuf],
+    format: TableFormat,
+) {
+    if !layouts
         .iter()
-        .step_by(50)
-        .map(|line| {
-            let (_, content) = line.split_once("\t").unwrap();
-            content
-                .to_lowercase()
-                .chars()
-                .filter(|c| c.is_ascii_alphabetic())
-                .collect::<String>()
-        })
-        .collect::<Vec<_>>()
-        .join("");
-    let mut letter_freq: HashMap<char, f64> = HashMap::new();
-    let mut bigrams: HashMap<String, f64> = HashMap::new();
-    let mut prev_c = None;
-    for line in raw_input.iter() {
-        for c in line.chars() {
-            let c = c.to_ascii_lowercase();
-            if !CHARS.contains(&c) {
-                continue;
-            }
-
-            *letter_freq.entry(c).or_default() += 1.0;
-
-            if let Some(prev_c) = prev_c {
-                let bigram = format!("{}{}", prev_c, c);
-                *bigrams.entry(bigram).or_default() += 1.0;
-            }
-
-            prev_c = Some(c);
-        }
+        .any(|layout| layout.name() == baseline.name())
+    {
+        layouts.insert(0, baseline);
     }
 
-    let len: f64 = letter_freq.values().sum();
-    for f in letter_freq.values_mut() {
-        *f /= len;
+    let mut keyboards = layouts
+        .iter()
+        .map(|layout| (layout.name(), layout.keyboard()))
+        .collect::<Vec<_>>();
+    for path in files {
+        keyboards.push(import_layout(path, key_rows));
     }
 
-    let len: f64 = bigrams.values().sum();
-    for b in bigrams.values_mut() {
-        *b /= len;
-    }
+    let corpora = if corpus_paths.is_empty() {
+        vec![("built in".to_string(), load_input(None))]
+    } else {
+        corpus_paths
+            .iter()
+            .map(|path| {
+                let corpus = std::fs::read_to_string(path)
+                    .unwrap_or_else(|err| panic!("could not read {}: {}", path.display(), err));
+                (path.display().to_string(), parse_corpus(&corpus, None))
+            })
+            .collect()
+    };
 
-    println!(
-        "qwerty: {}",
-        evaluate_individual(&QWERTY, &input, &letter_freq)
-    );
-    QWERTY.print_freq(&letter_freq);
-    println!(
-        "colemak: {}",
-        evaluate_individual(&COLEMAK, &input, &letter_freq)
-    );
-    COLEMAK.print_freq(&letter_freq);
-    println!(
-        "dvorak: {}",
-        evaluate_individual(&DVORAK, &input, &letter_freq)
-    );
-    DVORAK.print_freq(&letter_freq);
-    println!(
-        "miryoku: {}",
-        evaluate_individual(&MIRYOKU, &input, &letter_freq)
-    );
-    MIRYOKU.print_freq(&letter_freq);
-    println!(
-        "candidate 1: {}",
-        evaluate_individual(&CANDIDATE_1, &input, &letter_freq)
-    );
-    CANDIDATE_1.print_freq(&letter_freq);
+    let mut rows = Vec::new();
+    for (corpus, (input, letter_freq)) in &corpora {
+        for (name, keyboard) in &keyboards {
+            rows.push(compare::Row {
+                corpus: corpus.clone(),
+                layout: name.clone(),
+                metrics: measure(keyboard, input, letter_freq),
+            });
+        }
+    }
 
-    let mut population = generate_population();
+    print!("{}", compare::render(&rows, &baseline.name(), format));
+}
 
-    for g in 0..GENERATIONS {
-        let fitnesses = population
-            .par_iter()
-            .map(|individual| evaluate_individual(individual, &input, &letter_freq))
-            .collect::<Vec<_>>();
-        let mut fitnesses_with_index = fitnesses.iter().enumerate().collect::<Vec<_>>();
-        fitnesses_with_index.sort_by(|(_, left), (_, right)| {
-            left.partial_cmp(right).unwrap_or(std::cmp::Ordering::Equal)
-        });
+// The layout of a file, named after the file
+fn import_layout(path: &Path, key_rows: &[usize]) -> (String, Keyboard) {
+    let keyboard = import::load(path, key_rows).unwrap_or_else(|err| {
+        eprintln!("could not import {}: {}", path.display(), err);
+        std::process::exit(1)
+    });
+    let name = path.file_stem().map_or(path.display().to_string(), |stem| {
+        stem.to_string_lossy().to_string()
+    });
+    (name, keyboard)
+}
 
-        println!(
-            "[{}] best: {:.1}, avg: {:.1}",
-            g,
-            fitnesses_with_index[0].1,
-            fitnesses.iter().sum::<f64>() / POPULATION_SIZE as f64
-        );
-        let parent_indices = (0..POPULATION_SIZE)
-            .map(|_| tournament_selection(&fitnesses))
-            .collect::<Vec<_>>();
-        let mut new_population = parent_indices
-            .par_chunks(2)
-            .flat_map(|parent_indices| {
-                let first_individual = &population[parent_indices[0]];
-                let second_individual = &population[parent_indices[1]];
-
-                let (first_new, second_new) = cross(first_individual, second_individual);
-                vec![first_new, second_new]
-            })
-            .collect::<Vec<_>>();
-        new_population
-            .par_iter_mut()
-            .for_each(|individual| mutate(individual));
+// The seed layouts, leaving out the ones that break the hard constraints since the genetic
+// algorithm only ever creates layouts that satisfy them
+fn seeds(layouts: &[ReferenceLayout], files: &[PathBuf]) -> Vec<Keyboard> {
+    let mut seeds = layouts
+        .iter()
+        .map(|layout| (layout.name(), layout.keyboard()))
+        .collect::<Vec<_>>();
+    for path in files {
+        let (_, keyboard) = import_layout(path, &[]);
+        seeds.push((path.display().to_string(), keyboard));
+    }
 
-        new_population[0] = population[fitnesses_with_index[0].0].clone();
+    seeds
+        .into_iter()
+        .filter_map(|(name, keyboard)| {
+            if constraints::is_satisfied(&keyboard, CONSTRAINTS) {
+                Some(keyboard)
+            } else {
+                println!("{} breaks the hard constraints, not seeding it", name);
+                None
+            }
+        })
+        .collect()
+}
 
-        population = new_population;
+fn main() {
+    let args = Args::parse();
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    if let Some(Command::Compare {
+        layouts,
+        baseline,
+        files,
+        key_rows,
+        corpus,
+        format,
+    }) = args.command
+    {
+        compare(layouts, baseline, &files, &key_rows, &corpus, format);
+        return;
     }
 
-    let mut ranked_population = population
-        .into_par_iter()
-        .map(|individual| {
-            let fitness = evaluate_individual(&individual, &input, &letter_freq);
-            (individual, fitness)
-        })
-        .collect::<Vec<_>>();
-    ranked_population.sort_by(|(_, left), (_, right)| {
-        left.partial_cmp(right).unwrap_or(std::cmp::Ordering::Equal)
+    let command = args.command.unwrap_or(Command::Optimize { output: None });
+    let (input, letter_freq) = if command.is_random() {
+        // The seed to reproduce the run with
+        println!("seed: {}", seed);
+        load_input(Some(&mut rng))
+    } else {
+        load_input(None)
+    };
+    let seeds = seeds(&args.seed_layout, &args.seed_file);
+    let mut log = args.log.map(|path| {
+        RunLog::create(
+            &path,
+            args.log_format,
+            Run {
+                command: command.name(),
+                seed,
+                seeds: &seeds,
+                seed_share: args.seed_share,
+            },
+            &input,
+            &letter_freq,
+        )
+        .expect("could not create the run log")
     });
-    ranked_population[0].0.print();
-    ranked_population[0].0.print_freq(&letter_freq);
+    match command {
+        Command::Optimize { output } => optimize(
+            &input,
+            &letter_freq,
+            &seeds,
+            args.seed_share,
+            log.as_mut(),
+            output.as_deref(),
+            &mut rng,
+        ),
+        Command::Migrate { reference } => migrate(
+            &reference.keyboard(),
+            &input,
+            &letter_freq,
+            log.as_mut(),
+            &mut rng,
+        ),
+        Command::Pareto => pareto_front(&input, &letter_freq, &seeds, args.seed_share, &mut rng),
+        Command::Anneal { output } => anneal(&input, &letter_freq, output.as_deref(), &mut rng),
+        Command::Islands { output } => islands(
+            &input,
+            &letter_freq,
+            &seeds,
+            args.seed_share,
+            log.as_mut(),
+            output.as_deref(),
+            &mut rng,
+        ),
+        Command::Exact { reference } => exact(&reference.keyboard(), &input, &letter_freq),
+        Command::Evaluate {
+            reference,
+            file,
+            key_rows,
+            json,
+        } => {
+            let keyboard = match file {
+                Some(path) => import_layout(&path, &key_rows).1,
+                None => reference.keyboard(),
+            };
+            evaluate(&keyboard, &input, &letter_freq, json)
+        }
+        Command::Render {
+            reference,
+            file,
+            key_rows,
+            board,
+            coloring,
+            output,
+        } => {
+            let keyboard = match file {
+                Some(path) => import_layout(&path, &key_rows).1,
+                None => reference.keyboard(),
+            };
+            render(
+                &keyboard,
+                board,
+                coloring,
+                output.as_deref(),
+                &input,
+                &letter_freq,
+            )
+        }
+        Command::Export {
+            reference,
+            file,
+            key_rows,
+            format,
+            layout_macro,
+            board,
+            output,
+        } => {
+            let (name, keyboard) = match file {
+                Some(path) => import_layout(&path, &key_rows),
+                None => (reference.name(), reference.keyboard()),
+            };
+            export(
+                &name,
+                &keyboard,
+                format,
+                &layout_macro,
+                board,
+                output.as_deref(),
+            )
+        }
+        Command::Compare { .. } => unreachable!("compare loads its own corpora"),
+    }
 }
@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+
+use rand::{rngs::StdRng, Rng};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use kb_layout_evaluator::{constraints::Constraint, Keyboard, Metrics, TARGET_LEFT_HAND_USAGE};
+
+use crate::{genetic, GENERATIONS, POPULATION_SIZE};
+
+pub const OBJECTIVES: [&str; 4] = [
+    "same finger rate",
+    "distance per key",
+    "same hand no roll rate",
+    "hand imbalance",
+];
+
+/// The separate objectives the multi-objective optimizer minimizes, in the order of `OBJECTIVES`
+pub fn objectives(metrics: &Metrics) -> [f64; 4] {
+    let key_presses = metrics.key_presses() as f64;
+    [
+        metrics.same_finger_count as f64 / key_presses,
+        metrics.distance / key_presses,
+        metrics.same_hand_no_roll_count as f64 / key_presses,
+        (TARGET_LEFT_HAND_USAGE - metrics.left_hand_usage).abs(),
+    ]
+}
+
+pub struct Solution {
+    pub keyboard: Keyboard,
+    pub objectives: [f64; 4],
+    // Soft constraint penalty, a solution with a lower violation always dominates
+    pub violation: f64,
+    rank: usize,
+    crowding_distance: f64,
+}
+
+fn dominates(first: &Solution, second: &Solution) -> bool {
+    if first.violation != second.violation {
+        return first.violation < second.violation;
+    }
+
+    first
+        .objectives
+        .iter()
+        .zip(second.objectives.iter())
+        .all(|(first, second)| first <= second)
+        && first
+            .objectives
+            .iter()
+            .zip(second.objectives.iter())
+            .any(|(first, second)| first < second)
+}
+
+// Sorts the solutions into fronts of indices, where no solution in a front is dominated by a
+// solution in the same or a later front, and sets the rank of each solution
+fn non_dominated_sort(solutions: &mut [Solution]) -> Vec<Vec<usize>> {
+    let mut dominated_by = vec![Vec::new(); solutions.len()];
+    let mut domination_count = vec![0; solutions.len()];
+    let mut fronts = vec![Vec::new()];
+    for i in 0..solutions.len() {
+        for j in 0..solutions.len() {
+            if dominates(&solutions[i], &solutions[j]) {
+                dominated_by[i].push(j);
+            } else if dominates(&solutions[j], &solutions[i]) {
+                domination_count[i] += 1;
+            }
+        }
+
+        if domination_count[i] == 0 {
+            fronts[0].push(i);
+        }
+    }
+
+    while !fronts[fronts.len() - 1].is_empty() {
+        let mut next_front = Vec::new();
+        for &i in &fronts[fronts.len() - 1] {
+            solutions[i].rank = fronts.len() - 1;
+            for &j in &dominated_by[i] {
+                domination_count[j] -= 1;
+                if domination_count[j] == 0 {
+                    next_front.push(j);
+                }
+            }
+        }
+
+        fronts.push(next_front);
+    }
+
+    fronts.pop();
+    fronts
+}
+
+fn assign_crowding_distance(solutions: &mut [Solution], front: &[usize]) {
+    for &i in front {
+        solutions[i].crowding_distance = 0.0;
+    }
+
+    for objective in 0..OBJECTIVES.len() {
+        let mut front = front.to_vec();
+        front.sort_by(|&left, &right| {
+            solutions[left].objectives[objective]
+                .partial_cmp(&solutions[right].objectives[objective])
+                .unwrap_or(Ordering::Equal)
+        });
+        let min = solutions[front[0]].objectives[objective];
+        let max = solutions[front[front.len() - 1]].objectives[objective];
+        solutions[front[0]].crowding_distance = f64::INFINITY;
+        solutions[front[front.len() - 1]].crowding_distance = f64::INFINITY;
+        if max <= min {
+            continue;
+        }
+
+        for k in 1..front.len() - 1 {
+            solutions[front[k]].crowding_distance += (solutions[front[k + 1]].objectives
+                [objective]
+                - solutions[front[k - 1]].objectives[objective])
+                / (max - min);
+        }
+    }
+}
+
+// Binary tournament preferring lower rank, and less crowded solutions within the same rank
+fn crowded_tournament_selection(solutions: &[Solution], rng: &mut StdRng) -> usize {
+    let first = rng.gen::<usize>() % solutions.len();
+    let second = rng.gen::<usize>() % solutions.len();
+    match solutions[first].rank.cmp(&solutions[second].rank) {
+        Ordering::Less => first,
+        Ordering::Greater => second,
+        Ordering::Equal => {
+            if solutions[first].crowding_distance >= solutions[second].crowding_distance {
+                first
+            } else {
+                second
+            }
+        }
+    }
+}
+
+/// Runs NSGA-II for GENERATIONS generations and returns the first front, i.e. the layouts that
+/// no other layout beats on every objective
+pub fn run(
+    population: Vec<Keyboard>,
+    constraints: &[Constraint],
+    measure: impl Fn(&Keyboard) -> Metrics + Sync,
+    rng: &mut StdRng,
+) -> Vec<Solution> {
+    let evaluate = |keyboards: Vec<Keyboard>| {
+        keyboards
+            .into_par_iter()
+            .map(|keyboard| {
+                let metrics = measure(&keyboard);
+                Solution {
+                    objectives: objectives(&metrics),
+                    violation: metrics.soft_penalty,
+                    keyboard,
+                    rank: 0,
+                    crowding_distance: 0.0,
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut solutions = evaluate(population);
+    for front in non_dominated_sort(&mut solutions) {
+        assign_crowding_distance(&mut solutions, &front);
+    }
+
+    for g in 0..GENERATIONS {
+        let mut offspring = Vec::with_capacity(POPULATION_SIZE);
+        while offspring.len() < POPULATION_SIZE {
+            let first_individual =
+                &solutions[crowded_tournament_selection(&solutions, rng)].keyboard;
+            let second_individual =
+                &solutions[crowded_tournament_selection(&solutions, rng)].keyboard;
+            let (mut first_new, mut second_new) =
+                genetic::cross(first_individual, second_individual, constraints, rng);
+            genetic::mutate(&mut first_new, 1.0, constraints, rng);
+            genetic::mutate(&mut second_new, 1.0, constraints, rng);
+            offspring.push(first_new);
+            offspring.push(second_new);
+        }
+
+        solutions.extend(evaluate(offspring));
+        let fronts = non_dominated_sort(&mut solutions);
+        let mut survivors = Vec::with_capacity(POPULATION_SIZE);
+        for front in fronts {
+            assign_crowding_distance(&mut solutions, &front);
+            let mut front = front;
+            if survivors.len() + front.len() > POPULATION_SIZE {
+                front.sort_by(|&left, &right| {
+                    solutions[right]
+                        .crowding_distance
+                        .partial_cmp(&solutions[left].crowding_distance)
+                        .unwrap_or(Ordering::Equal)
+                });
+                front.truncate(POPULATION_SIZE - survivors.len());
+            }
+
+            survivors.extend(front);
+            if survivors.len() == POPULATION_SIZE {
+                break;
+            }
+        }
+
+        survivors.sort_unstable();
+        solutions = solutions
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| survivors.binary_search(i).is_ok())
+            .map(|(_, solution)| solution)
+            .collect();
+
+        let first_front_size = solutions
+            .iter()
+            .filter(|solution| solution.rank == 0)
+            .count();
+        println!("[{}] first front: {}", g, first_front_size);
+    }
+
+    let mut front = solutions
+        .into_iter()
+        .filter(|solution| solution.rank == 0)
+        .collect::<Vec<_>>();
+    front.sort_by(|left, right| {
+        left.objectives
+            .partial_cmp(&right.objectives)
+            .unwrap_or(Ordering::Equal)
+    });
+    front.dedup_by(|left, right| left.keyboard == right.keyboard);
+    front
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solution(objectives: [f64; 4], violation: f64) -> Solution {
+        Solution {
+            keyboard: Keyboard::default(),
+            objectives,
+            violation,
+            rank: 0,
+            crowding_distance: 0.0,
+        }
+    }
+
+    #[test]
+    fn sorts_into_fronts() {
+        let mut solutions = vec![
+            solution([1.0, 1.0, 1.0, 1.0], 0.0),
+            solution([2.0, 2.0, 2.0, 2.0], 0.0),
+            // Better than the first on one objective and worse on another
+            solution([0.0, 3.0, 1.0, 1.0], 0.0),
+            solution([3.0, 3.0, 3.0, 3.0], 0.0),
+            // Better on every objective, but violates the soft constraints more
+            solution([0.0, 0.0, 0.0, 0.0], 0.5),
+        ];
+        let fronts = non_dominated_sort(&mut solutions);
+        assert_eq!(fronts, vec![vec![0, 2], vec![1], vec![3], vec![4]]);
+        let ranks = solutions
+            .iter()
+            .map(|solution| solution.rank)
+            .collect::<Vec<_>>();
+        assert_eq!(ranks, [0, 1, 0, 2, 3]);
+    }
+
+    #[test]
+    fn crowding_distance() {
+        let mut solutions = vec![
+            solution([0.0, 4.0, 0.0, 0.0], 0.0),
+            solution([1.0, 3.0, 0.0, 0.0], 0.0),
+            solution([2.0, 1.0, 0.0, 0.0], 0.0),
+            solution([4.0, 0.0, 0.0, 0.0], 0.0),
+        ];
+        assign_crowding_distance(&mut solutions, &[0, 1, 2, 3]);
+        // The boundaries of the front are kept, the others get the sum over the objectives of the
+        // distance between their neighbours relative to the range of the objective
+        assert_eq!(solutions[0].crowding_distance, f64::INFINITY);
+        assert_eq!(solutions[3].crowding_distance, f64::INFINITY);
+        assert!((solutions[1].crowding_distance - (2.0 / 4.0 + 3.0 / 4.0)).abs() < 1e-12);
+        assert!((solutions[2].crowding_distance - (3.0 / 4.0 + 3.0 / 4.0)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use kb_layout_evaluator::{
+    measure, Keyboard, Metrics, BOTTOM_ROW_PENALTY, FINGER_TARGET_USAGE, MOUSE_HAND,
+    MOUSE_SHORTCUT_PENALTY, SAME_FINGER_PENALTY, SAME_HAND_PENALTY, TARGET_LEFT_HAND_USAGE,
+};
+
+use crate::{
+    ADAPTIVE_MUTATION, ANNEALING_COOLING, ANNEALING_ITERATIONS, ANNEALING_RESTARTS,
+    CROSSOVER_OPERATOR, CROSSOVER_PROBABILITY, DIVERSITY_THRESHOLD, GENERATIONS,
+    HILL_CLIMBING_THREE_CYCLES, ISLANDS, MAX_MUTATION_SCALE, MIGRANTS, MIGRATION_INTERVAL,
+    MIGRATION_TOPOLOGY, MUTATION_PROBABILITY, MUTATION_PROBABILITY_CONSTRAINED,
+    MUTATION_SCALE_STEP, POPULATION_SIZE, REPLACEMENT, SELECTION, SIMILARITY_WEIGHT,
+    STAGNATION_GENERATIONS, TARGET_FITNESS, TIME_BUDGET, TOURNAMENT_PROBABILTY, TOURNAMENT_SIZE,
+    TRUNCATION_SHARE,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// One JSON object per line, the first line is the header
+    Jsonl,
+    /// Comma separated values, the header is a JSON comment line before the column names
+    Csv,
+}
+
+/// What a run was started with, next to the settings
+pub struct Run<'a> {
+    pub command: &'a str,
+    pub seed: u64,
+    /// Layouts the initial population is seeded with
+    pub seeds: &'a [Keyboard],
+    pub seed_share: f64,
+}
+
+// Settings the run was made with, written once at the top of the log
+#[derive(Serialize)]
+struct Header<'a> {
+    command: &'a str,
+    seed: u64,
+    seed_layouts: Vec<String>,
+    seed_share: f64,
+    population_size: usize,
+    generations: usize,
+    selection: String,
+    tournament_probability: f64,
+    tournament_size: usize,
+    truncation_share: f64,
+    replacement: String,
+    mutation_probability: f64,
+    mutation_probability_constrained: f64,
+    adaptive_mutation: bool,
+    diversity_threshold: f64,
+    mutation_scale_step: f64,
+    max_mutation_scale: f64,
+    crossover_probability: f64,
+    crossover_operator: String,
+    stagnation_generations: Option<usize>,
+    time_budget_seconds: Option<f64>,
+    target_fitness: Option<f64>,
+    islands: usize,
+    migration_interval: usize,
+    migrants: usize,
+    migration_topology: String,
+    similarity_weight: f64,
+    annealing_restarts: usize,
+    annealing_iterations: usize,
+    annealing_cooling: String,
+    hill_climbing_three_cycles: bool,
+    same_finger_penalty: f64,
+    same_hand_penalty: f64,
+    bottom_row_penalty: f64,
+    finger_target_usage: [f64; 10],
+    target_left_hand_usage: f64,
+    mouse_hand: Option<String>,
+    mouse_shortcut_penalty: f64,
+}
+
+/// Statistics of one generation of the genetic algorithm
+pub struct Generation<'a> {
+    pub generation: usize,
+    pub population: &'a [Keyboard],
+    pub fitnesses: &'a [f64],
+    pub diversity: f64,
+    pub entropy: f64,
+    pub mutation_scale: f64,
+}
+
+#[derive(Serialize)]
+struct GenerationRecord<'a> {
+    generation: usize,
+    best: f64,
+    avg: f64,
+    worst: f64,
+    diversity: f64,
+    entropy: f64,
+    mutation_scale: f64,
+    // Breakdown of the best layout, without anything the caller adds to the fitness
+    best_fitness_unweighted: f64,
+    best_metrics: &'a Metrics,
+    best_layout: String,
+    generation_seconds: f64,
+    elapsed_seconds: f64,
+}
+
+/// Writes per generation statistics of a run to a file, so that convergence can be plotted
+/// without scraping stdout
+pub struct RunLog<'a> {
+    writer: BufWriter<File>,
+    format: LogFormat,
+    input: &'a str,
+    letter_freq: &'a HashMap<char, f64>,
+    start: Instant,
+    last_record: Instant,
+}
+
+impl<'a> RunLog<'a> {
+    /// Creates the log file and writes the header with the current settings and what the run was
+    /// started with
+    pub fn create(
+        path: &Path,
+        format: LogFormat,
+        run: Run,
+        input: &'a str,
+        letter_freq: &'a HashMap<char, f64>,
+    ) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let header = serde_json::to_string(&Header {
+            command: run.command,
+            seed: run.seed,
+            seed_layouts: run.seeds.iter().map(layout_string).collect(),
+            seed_share: run.seed_share,
+            population_size: POPULATION_SIZE,
+            generations: GENERATIONS,
+            selection: format!("{:?}", SELECTION),
+            tournament_probability: TOURNAMENT_PROBABILTY,
+            tournament_size: TOURNAMENT_SIZE,
+            truncation_share: TRUNCATION_SHARE,
+            replacement: format!("{:?}", REPLACEMENT),
+            mutation_probability: MUTATION_PROBABILITY,
+            mutation_probability_constrained: MUTATION_PROBABILITY_CONSTRAINED,
+            adaptive_mutation: ADAPTIVE_MUTATION,
+            diversity_threshold: DIVERSITY_THRESHOLD,
+            mutation_scale_step: MUTATION_SCALE_STEP,
+            max_mutation_scale: MAX_MUTATION_SCALE,
+            crossover_probability: CROSSOVER_PROBABILITY,
+            crossover_operator: format!("{:?}", CROSSOVER_OPERATOR),
+            stagnation_generations: STAGNATION_GENERATIONS,
+            time_budget_seconds: TIME_BUDGET.map(|budget| budget.as_secs_f64()),
+            target_fitness: TARGET_FITNESS,
+            islands: ISLANDS,
+            migration_interval: MIGRATION_INTERVAL,
+            migrants: MIGRANTS,
+            migration_topology: format!("{:?}", MIGRATION_TOPOLOGY),
+            similarity_weight: SIMILARITY_WEIGHT,
+            annealing_restarts: ANNEALING_RESTARTS,
+            annealing_iterations: ANNEALING_ITERATIONS,
+            annealing_cooling: format!("{:?}", ANNEALING_COOLING),
+            hill_climbing_three_cycles: HILL_CLIMBING_THREE_CYCLES,
+            same_finger_penalty: SAME_FINGER_PENALTY,
+            same_hand_penalty: SAME_HAND_PENALTY,
+            bottom_row_penalty: BOTTOM_ROW_PENALTY,
+            finger_target_usage: FINGER_TARGET_USAGE,
+            target_left_hand_usage: TARGET_LEFT_HAND_USAGE,
+            mouse_hand: MOUSE_HAND.map(|hand| format!("{:?}", hand)),
+            mouse_shortcut_penalty: MOUSE_SHORTCUT_PENALTY,
+        })?;
+        match format {
+            LogFormat::Jsonl => writeln!(writer, "{}", header)?,
+            LogFormat::Csv => {
+                writeln!(writer, "# {}", header)?;
+                writeln!(
+                    writer,
+                    "generation,best,avg,worst,diversity,entropy,mutation_scale,\
+                     best_fitness_unweighted,distance,same_finger_count,same_hand_no_roll_count,\
+                     {},left_hand_usage,unreachable_shortcut_count,soft_penalty,best_layout,\
+                     generation_seconds,elapsed_seconds",
+                    (0..10)
+                        .map(|i| format!("finger_usage_{}", i))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )?;
+            }
+        }
+
+        let now = Instant::now();
+        Ok(Self {
+            writer,
+            format,
+            input,
+            letter_freq,
+            start: now,
+            last_record: now,
+        })
+    }
+
+    pub fn record(&mut self, generation: Generation) -> io::Result<()> {
+        let now = Instant::now();
+        let generation_seconds = (now - self.last_record).as_secs_f64();
+        let elapsed_seconds = (now - self.start).as_secs_f64();
+        self.last_record = now;
+
+        let fitnesses = generation.fitnesses;
+        let best_index = (0..fitnesses.len())
+            .min_by(|&left, &right| {
+                fitnesses[left]
+                    .partial_cmp(&fitnesses[right])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        let best_layout = &generation.population[best_index];
+        let best_metrics = measure(best_layout, self.input, self.letter_freq);
+        let record = GenerationRecord {
+            generation: generation.generation,
+            best: fitnesses[best_index],
+            avg: fitnesses.iter().sum::<f64>() / fitnesses.len() as f64,
+            worst: fitnesses.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            diversity: generation.diversity,
+            entropy: generation.entropy,
+            mutation_scale: generation.mutation_scale,
+            best_fitness_unweighted: best_metrics.fitness(),
+            best_metrics: &best_metrics,
+            best_layout: layout_string(best_layout),
+            generation_seconds,
+            elapsed_seconds,
+        };
+        match self.format {
+            LogFormat::Jsonl => writeln!(self.writer, "{}", serde_json::to_string(&record)?)?,
+            LogFormat::Csv => writeln!(
+                self.writer,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},\"{}\",{},{}",
+                record.generation,
+                record.best,
+                record.avg,
+                record.worst,
+                record.diversity,
+                record.entropy,
+                record.mutation_scale,
+                record.best_fitness_unweighted,
+                best_metrics.distance,
+                best_metrics.same_finger_count,
+                best_metrics.same_hand_no_roll_count,
+                best_metrics
+                    .finger_usage
+                    .iter()
+                    .map(|usage| usage.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                best_metrics.left_hand_usage,
+                best_metrics.unreachable_shortcut_count,
+                best_metrics.soft_penalty,
+                record.best_layout,
+                record.generation_seconds,
+                record.elapsed_seconds
+            )?,
+        }
+
+        self.writer.flush()
+    }
+}
+
+// The keys row by row, with rows separated by slashes, e.g. "qwertyuiop/asdfghjkl;/zxcvbnm,.?"
+fn layout_string(keyboard: &Keyboard) -> String {
+    (0..3)
+        .map(|y| (0..10).map(|x| keyboard.keys[x][y]).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("/")
+}
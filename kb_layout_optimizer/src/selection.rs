@@ -0,0 +1,124 @@
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, Rng};
+
+use crate::{Keyboard, TOURNAMENT_PROBABILTY, TOURNAMENT_SIZE, TRUNCATION_SHARE};
+
+#[derive(Clone, Copy, Debug)]
+// Not every strategy is used by the default settings
+#[allow(dead_code)]
+pub enum Selection {
+    /// Probabilistic tournaments between TOURNAMENT_SIZE random individuals
+    Tournament,
+    /// Linear ranking, the best individual is picked n times as often as the worst
+    Rank,
+    /// Fitness proportionate, with the inverse of the fitness as lower fitness is better
+    Roulette,
+    /// Uniformly among the best TRUNCATION_SHARE of the population
+    Truncation,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[allow(dead_code)]
+pub enum Replacement {
+    /// The offspring replace the population, except for the best individual which is kept
+    Generational,
+    /// (mu+lambda), parents and offspring compete and the best of them survive
+    MuPlusLambda,
+}
+
+/// Indices of `count` parents picked from the population with the given fitnesses
+pub fn select_parents(
+    selection: Selection,
+    fitnesses: &[f64],
+    count: usize,
+    rng: &mut StdRng,
+) -> Vec<usize> {
+    match selection {
+        Selection::Tournament => (0..count)
+            .map(|_| tournament_selection(fitnesses, rng))
+            .collect(),
+        Selection::Rank => {
+            let ranked_indices = ranked_indices(fitnesses);
+            let weights = (0..fitnesses.len()).rev().map(|rank| rank + 1);
+            let distribution = WeightedIndex::new(weights).unwrap();
+            (0..count)
+                .map(|_| ranked_indices[distribution.sample(rng)])
+                .collect()
+        }
+        Selection::Roulette => {
+            let weights = fitnesses
+                .iter()
+                .map(|fitness| 1.0 / fitness.max(f64::MIN_POSITIVE));
+            let distribution = WeightedIndex::new(weights).unwrap();
+            (0..count).map(|_| distribution.sample(rng)).collect()
+        }
+        Selection::Truncation => {
+            let ranked_indices = ranked_indices(fitnesses);
+            let truncated_len =
+                ((fitnesses.len() as f64 * TRUNCATION_SHARE).ceil() as usize).max(1);
+            (0..count)
+                .map(|_| ranked_indices[rng.gen_range(0..truncated_len)])
+                .collect()
+        }
+    }
+}
+
+/// The individuals that make up the next generation along with their fitnesses
+pub fn replace(
+    replacement: Replacement,
+    population: Vec<Keyboard>,
+    fitnesses: Vec<f64>,
+    mut offspring: Vec<Keyboard>,
+    mut offspring_fitnesses: Vec<f64>,
+) -> (Vec<Keyboard>, Vec<f64>) {
+    match replacement {
+        Replacement::Generational => {
+            let best_index = ranked_indices(&fitnesses)[0];
+            offspring[0] = population[best_index].clone();
+            offspring_fitnesses[0] = fitnesses[best_index];
+            (offspring, offspring_fitnesses)
+        }
+        Replacement::MuPlusLambda => {
+            let population_size = population.len();
+            let mut individuals = population
+                .into_iter()
+                .zip(fitnesses)
+                .chain(offspring.into_iter().zip(offspring_fitnesses))
+                .collect::<Vec<_>>();
+            individuals.sort_by(|(_, left), (_, right)| {
+                left.partial_cmp(right).unwrap_or(std::cmp::Ordering::Equal)
+            });
+            individuals.truncate(population_size);
+            individuals.into_iter().unzip()
+        }
+    }
+}
+
+// Indices of the individuals, best first
+fn ranked_indices(fitnesses: &[f64]) -> Vec<usize> {
+    let mut indices = (0..fitnesses.len()).collect::<Vec<_>>();
+    indices.sort_by(|&left, &right| {
+        fitnesses[left]
+            .partial_cmp(&fitnesses[right])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    indices
+}
+
+fn tournament_selection(fitnesses: &[f64], rng: &mut StdRng) -> usize {
+    let mut indices = Vec::with_capacity(TOURNAMENT_SIZE);
+    for _ in 0..TOURNAMENT_SIZE {
+        indices.push(rng.gen::<usize>() % fitnesses.len());
+    }
+
+    while indices.len() > 1 {
+        let index = indices.pop().unwrap();
+        if rng.gen::<f64>() < TOURNAMENT_PROBABILTY {
+            // lower is better
+            if fitnesses[index] < fitnesses[indices[indices.len() - 1]] {
+                return index;
+            }
+        }
+    }
+
+    indices.pop().unwrap()
+}
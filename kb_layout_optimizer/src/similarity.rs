@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use kb_layout_evaluator::{Keyboard, CHARS};
+
+/// Number of keys that are on a different position than in the reference
+pub fn moved_keys(keyboard: &Keyboard, reference: &Keyboard) -> usize {
+    CHARS
+        .into_iter()
+        .filter(|&key| keyboard.key_pos(key) != reference.key_pos(key))
+        .count()
+}
+
+/// Share of key presses that are on a different position than in the reference, i.e. moved keys
+/// weighted by letter frequency
+pub fn weighted_distance(
+    keyboard: &Keyboard,
+    reference: &Keyboard,
+    letter_freq: &HashMap<char, f64>,
+) -> f64 {
+    CHARS
+        .into_iter()
+        .filter(|&key| keyboard.key_pos(key) != reference.key_pos(key))
+        .fold(0.0, |distance, key| {
+            distance + letter_freq.get(&key).unwrap()
+        })
+}
+
+/// The best layout seen for each number of keys moved from the reference
+pub struct MigrationArchive {
+    best: Vec<Option<(Keyboard, f64)>>,
+}
+
+impl MigrationArchive {
+    pub fn new() -> Self {
+        Self {
+            best: vec![None; CHARS.len() + 1],
+        }
+    }
+
+    pub fn insert(&mut self, keyboard: &Keyboard, moved_keys: usize, fitness: f64) {
+        match &self.best[moved_keys] {
+            Some((_, best_fitness)) if *best_fitness <= fitness => {}
+            _ => self.best[moved_keys] = Some((keyboard.clone(), fitness)),
+        }
+    }
+
+    /// The best layout for each budget of moved keys, only budgets that improve on every smaller
+    /// budget are included
+    pub fn budgets(&self) -> Vec<(usize, &Keyboard, f64)> {
+        let mut budgets: Vec<(usize, &Keyboard, f64)> = Vec::new();
+        for (moved_keys, best) in self.best.iter().enumerate() {
+            if let Some((keyboard, fitness)) = best {
+                if budgets
+                    .last()
+                    .is_none_or(|&(_, _, best_fitness)| *fitness < best_fitness)
+                {
+                    budgets.push((moved_keys, keyboard, *fitness));
+                }
+            }
+        }
+
+        budgets
+    }
+}
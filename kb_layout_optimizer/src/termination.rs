@@ -0,0 +1,61 @@
+use std::{fmt, time::Instant};
+
+use crate::{STAGNATION_GENERATIONS, TARGET_FITNESS, TIME_BUDGET};
+
+/// Why a run stopped before reaching GENERATIONS
+pub enum StopReason {
+    Stagnation(usize),
+    TimeBudget,
+    TargetFitness(f64),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Stagnation(generations) => {
+                write!(f, "no improvement in {} generations", generations)
+            }
+            Self::TimeBudget => write!(f, "time budget of {:?} used up", TIME_BUDGET.unwrap()),
+            Self::TargetFitness(fitness) => write!(f, "reached target fitness {:.1}", fitness),
+        }
+    }
+}
+
+/// Keeps track of the best fitness of a run to tell when it should stop, according to
+/// STAGNATION_GENERATIONS, TIME_BUDGET and TARGET_FITNESS
+pub struct Termination {
+    start: Instant,
+    best_fitness: f64,
+    stagnant_generations: usize,
+}
+
+impl Termination {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            best_fitness: f64::INFINITY,
+            stagnant_generations: 0,
+        }
+    }
+
+    /// Records the best fitness of the current generation, lower is better, and returns why the
+    /// run should stop if it should
+    pub fn update(&mut self, best_fitness: f64) -> Option<StopReason> {
+        if best_fitness < self.best_fitness {
+            self.best_fitness = best_fitness;
+            self.stagnant_generations = 0;
+        } else {
+            self.stagnant_generations += 1;
+        }
+
+        if TARGET_FITNESS.is_some_and(|target| self.best_fitness <= target) {
+            Some(StopReason::TargetFitness(self.best_fitness))
+        } else if STAGNATION_GENERATIONS.is_some_and(|limit| self.stagnant_generations >= limit) {
+            Some(StopReason::Stagnation(self.stagnant_generations))
+        } else if TIME_BUDGET.is_some_and(|budget| self.start.elapsed() >= budget) {
+            Some(StopReason::TimeBudget)
+        } else {
+            None
+        }
+    }
+}
@@ -1,4 +1,120 @@
-#[no_mangle]
-pub extern "C" fn add_one(x: i32) -> i32 {
-    x + 1
+use kb_layout::{Key, Keyboard, Layer, Layout, MatrixPosition, Position};
+use kb_layout_evaluator::{breakdown, ngrams::Ngrams, parse_corpus, physical};
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+// The Button interface of the editor
+#[derive(Deserialize)]
+struct Button {
+    hand: Hand,
+    finger: Finger,
+    position: Point,
+    matrix_position: MatrixPoint,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+enum Hand {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+enum Finger {
+    Thumb,
+    Pointer,
+    Middle,
+    Ring,
+    Pinky,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+struct MatrixPoint {
+    x: u8,
+    y: u8,
+}
+
+/// Counts the n-grams of a corpus, as text with one sentence per line, into the table `evaluate`
+/// takes. Returns the table as JSON, see `ngrams::Ngrams`
+#[wasm_bindgen]
+pub fn ngrams(corpus: &str) -> Result<String, JsError> {
+    let (input, letter_freq) = parse_corpus(corpus, None);
+    Ok(serde_json::to_string(&Ngrams::count(&input, letter_freq))?)
+}
+
+/// Evaluates a layout on a keyboard of the editor with the evaluator of the optimizer. Takes JSON
+/// for the buttons and the character each button types in the same order (empty for buttons
+/// without one), and the n-gram table of `ngrams`. Like in the optimizer, the layout is evaluated
+/// on its 3x10 grid, found through the matrix positions of the buttons (see `physical::grid`), so
+/// the score doesn't depend on where the buttons are or which fingers type them. Returns the
+/// breakdown of the fitness as JSON, see `breakdown::ngram_breakdown`
+#[wasm_bindgen]
+pub fn evaluate(buttons: &str, layout: &str, ngrams: &str) -> Result<String, JsError> {
+    let ngrams = serde_json::from_str::<Ngrams>(ngrams)?;
+    let buttons = serde_json::from_str::<Vec<Button>>(buttons)?;
+    let layout = serde_json::from_str::<Vec<String>>(layout)?;
+    if layout.len() != buttons.len() {
+        return Err(JsError::new(&format!(
+            "the layout has {} keys but the keyboard has {} buttons",
+            layout.len(),
+            buttons.len()
+        )));
+    }
+
+    let keys = layout
+        .iter()
+        .map(|key| {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Key::Char(c)),
+                (None, _) => Ok(Key::None),
+                _ => Err(JsError::new(&format!("{} is not a single character", key))),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let layout = Layout {
+        layers: vec![Layer {
+            name: "base".to_string(),
+            keys,
+        }],
+    };
+    let grid = physical::grid(&keyboard(&buttons), &layout).map_err(|err| JsError::new(&err))?;
+    let breakdown = breakdown::ngram_breakdown(&grid, &ngrams);
+    Ok(serde_json::to_string(&breakdown)?)
+}
+
+// The buttons have no home offset or cost since the score doesn't depend on them
+fn keyboard(buttons: &[Button]) -> Keyboard {
+    let buttons = buttons
+        .iter()
+        .map(|button| kb_layout::Button {
+            hand: match button.hand {
+                Hand::Left => kb_layout::Hand::Left,
+                Hand::Right => kb_layout::Hand::Right,
+            },
+            finger: match button.finger {
+                Finger::Thumb => kb_layout::Finger::Thumb,
+                Finger::Pointer => kb_layout::Finger::Pointer,
+                Finger::Middle => kb_layout::Finger::Middle,
+                Finger::Ring => kb_layout::Finger::Ring,
+                Finger::Pinky => kb_layout::Finger::Pinky,
+            },
+            position: Position {
+                x: button.position.x,
+                y: button.position.y,
+            },
+            matrix_position: MatrixPosition {
+                col: button.matrix_position.x,
+                row: button.matrix_position.y,
+            },
+            home_offset: Position { x: 0.0, y: 0.0 },
+            cost: 0.0,
+        })
+        .collect();
+    Keyboard { buttons }
 }